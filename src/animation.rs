@@ -0,0 +1,96 @@
+use crate::vec3::Point3;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    CatmullRom,
+}
+
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self>
+{
+}
+impl<T> Animatable for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T> {}
+
+// Keyframes sorted by time, held constant before the first and after the last key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track<T> {
+    keys: Vec<(f64, T)>,
+    pub interpolation: Interpolation,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            interpolation: Interpolation::default(),
+        }
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        let index = self.keys.partition_point(|(t, _)| *t <= time);
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn evaluate(&self, time: f64) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        if time >= last.0 {
+            return Some(last.1);
+        }
+
+        let i = self.keys.partition_point(|(t, _)| *t <= time) - 1;
+        let ((t0, p0), (t1, p1)) = (self.keys[i], self.keys[i + 1]);
+        let dt = t1 - t0;
+        let s = (time - t0) / dt;
+
+        match self.interpolation {
+            Interpolation::Linear => Some(p0 + (p1 - p0) * s),
+            Interpolation::CatmullRom => {
+                // Cubic Hermite with Catmull-Rom tangents, scaled for uneven key spacing
+                let tangent = |j: usize| {
+                    let (prev, next) = (j.saturating_sub(1), (j + 1).min(self.keys.len() - 1));
+                    let (tp, pp) = self.keys[prev];
+                    let (tn, pn) = self.keys[next];
+                    (pn - pp) * (dt / (tn - tp))
+                };
+                let (m0, m1) = (tangent(i), tangent(i + 1));
+
+                let (s2, s3) = (s * s, s * s * s);
+                Some(
+                    p0 * (2.0 * s3 - 3.0 * s2 + 1.0)
+                        + m0 * (s3 - 2.0 * s2 + s)
+                        + p1 * (3.0 * s2 - 2.0 * s3)
+                        + m1 * (s3 - s2),
+                )
+            }
+        }
+    }
+}
+
+// Times are in frames
+#[derive(Default, Debug, Clone)]
+pub struct CameraTrack {
+    pub look_from: Track<Point3>,
+    pub look_at: Track<Point3>,
+    pub fov: Track<f64>,
+    pub focus_dist: Track<f64>,
+}
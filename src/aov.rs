@@ -0,0 +1,120 @@
+use crate::{
+    color::Color,
+    denoise::Denoiser,
+    image::{self, ImageInfo},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error},
+    path::Path,
+};
+
+// Arbitrary output variables taken from the first hit of each camera ray. Lobe passes split
+// the beauty by the first scattering event, misses go to `Emission` and leave the rest black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    Uv,
+    MaterialId,
+    ObjectId,
+    Diffuse,
+    Specular,
+    Transmission,
+    Emission,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 11] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Uv,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Diffuse,
+        Aov::Specular,
+        Aov::Transmission,
+        Aov::Emission,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Diffuse => "diffuse",
+            Aov::Specular => "specular",
+            Aov::Transmission => "transmission",
+            Aov::Emission => "emission",
+        }
+    }
+}
+
+// Stable pseudo-random color for an ID so that neighbouring IDs are easy to tell apart
+pub fn id_color(id: u64) -> Color {
+    let mut z = id.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+    Color(
+        (z & 0xFF) as f64 / 255.0,
+        ((z >> 8) & 0xFF) as f64 / 255.0,
+        ((z >> 16) & 0xFF) as f64 / 255.0,
+    )
+}
+
+pub struct RenderPasses {
+    pub image_info: ImageInfo,
+    pub beauty: Vec<Color>,
+    pub aovs: Vec<(Aov, Vec<Color>)>,
+}
+
+impl RenderPasses {
+    pub fn aov(&self, aov: Aov) -> Option<&[Color]> {
+        self.aovs
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    // Filters the beauty in place, guided by the albedo and normal passes when present
+    pub fn denoise(&mut self, denoiser: &Denoiser) {
+        self.beauty = denoiser.denoise(
+            &self.image_info,
+            &self.beauty,
+            self.aov(Aov::Albedo),
+            self.aov(Aov::Normal),
+        );
+    }
+
+    // All passes as layers of one OpenEXR file
+    pub fn write_exr(&self, path: &Path) -> Result<(), Error> {
+        let layers: Vec<(&str, &[Color])> = std::iter::once(("beauty", self.beauty.as_slice()))
+            .chain(
+                self.aovs
+                    .iter()
+                    .map(|(aov, pixels)| (aov.name(), pixels.as_slice())),
+            )
+            .collect();
+        image::write_exr(path, &self.image_info, &layers)
+    }
+
+    // One PFM per pass in `directory`, named `beauty.pfm`, `albedo.pfm` and so on
+    pub fn write_separate(&self, directory: &Path) -> Result<(), Error> {
+        let passes = std::iter::once(("beauty", &self.beauty))
+            .chain(self.aovs.iter().map(|(aov, pixels)| (aov.name(), pixels)));
+        for (name, pixels) in passes {
+            let mut file = BufWriter::new(File::create(directory.join(format!("{}.pfm", name)))?);
+            image::write_pfm(&mut file, &self.image_info, pixels)?;
+        }
+        Ok(())
+    }
+}
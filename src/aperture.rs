@@ -0,0 +1,93 @@
+use crate::{
+    color::luminance,
+    degrees_to_radians,
+    image::{self, ImageInfo},
+    sampling::Distribution2D,
+    vec3::{random_int_unit_disk, Vec3},
+};
+use std::{f64::consts::PI, io::Read, sync::Arc};
+
+// Lens aperture shape, sampled over [-1, 1]^2 and scaled by the defocus disk.
+#[derive(Default, Debug, Clone)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => random_int_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                // Blades span equal-area triangles around the center, so pick one uniformly
+                let blades = (*blades).max(3);
+                let step = 2.0 * PI / blades as f64;
+                let k = fastrand::u32(0..blades) as f64;
+                let theta0 = degrees_to_radians(*rotation) + k * step;
+                let (a, b) = (
+                    Vec3(theta0.cos(), theta0.sin(), 0.0),
+                    Vec3((theta0 + step).cos(), (theta0 + step).sin(), 0.0),
+                );
+
+                let (mut s, mut t) = (fastrand::f64(), fastrand::f64());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                s * a + t * b
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApertureMask {
+    image_info: ImageInfo,
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    // `transmission` holds one value per pixel in row-major order, top row first
+    pub fn new(image_info: &ImageInfo, transmission: &[f64]) -> Self {
+        let distribution = Distribution2D::new(
+            transmission,
+            image_info.image_width as usize,
+            image_info.image_height as usize,
+        );
+        assert!(
+            distribution.integral() > 0.0,
+            "Aperture mask is fully opaque"
+        );
+        Self {
+            image_info: *image_info,
+            distribution,
+        }
+    }
+
+    pub fn from_pnm(reader: &mut dyn Read) -> Result<Self, std::io::Error> {
+        let (image_info, pixels) = image::read_pnm(reader)?;
+        let transmission: Vec<f64> = pixels.iter().map(luminance).collect();
+        // A mask file with no light through it is bad input rather than a bug
+        if !transmission.iter().any(|&t| t > 0.0) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Aperture mask is fully opaque",
+            ));
+        }
+        Ok(Self::new(&image_info, &transmission))
+    }
+
+    pub fn image_info(&self) -> ImageInfo {
+        self.image_info
+    }
+
+    fn sample(&self) -> Vec3 {
+        let ((x, y), _) = self.distribution.sample(fastrand::f64(), fastrand::f64());
+        Vec3(2.0 * x - 1.0, 1.0 - 2.0 * y, 0.0)
+    }
+}
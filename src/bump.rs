@@ -0,0 +1,112 @@
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::{Material, ScatterRecord},
+    onb::Onb,
+    ray::Ray,
+    texture::ImageTexture,
+    vec3::{dot, unit_vector, Vec3},
+};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum Perturbation {
+    // Tangent-space normals stored as colors, with red along the tangent, green towards
+    // increasing v and blue along the surface normal
+    NormalMap(Arc<ImageTexture>),
+    // Heights taken from the luminance, in units of the (u, v) square
+    Bump(Arc<ImageTexture>),
+}
+
+// Surface detail on top of `base`, added by bending its shading normal rather than the
+// geometry. `strength` scales the tilt, with 0 leaving the surface smooth. Surfaces without
+// a tangent have no frame to orient the map in and are left alone.
+pub struct Perturbed {
+    pub base: Arc<dyn Material>,
+    pub perturbation: Perturbation,
+    pub strength: f64,
+}
+
+impl Perturbed {
+    pub fn normal_map(base: Arc<dyn Material>, texture: Arc<ImageTexture>) -> Self {
+        Self {
+            base,
+            perturbation: Perturbation::NormalMap(texture),
+            strength: 1.0,
+        }
+    }
+
+    pub fn bump(base: Arc<dyn Material>, texture: Arc<ImageTexture>) -> Self {
+        Self {
+            base,
+            perturbation: Perturbation::Bump(texture),
+            strength: 1.0,
+        }
+    }
+
+    pub fn strength(self, strength: f64) -> Self {
+        Self { strength, ..self }
+    }
+
+    // Perturbed normal in the tangent frame of the unperturbed one
+    fn local_normal(&self, u: f64, v: f64) -> Vec3 {
+        match &self.perturbation {
+            Perturbation::NormalMap(texture) => {
+                let n = 2.0 * texture.value(u, v) - Color(1.0, 1.0, 1.0);
+                Vec3(self.strength * n.x(), self.strength * n.y(), n.z().max(0.0))
+            }
+            Perturbation::Bump(texture) => {
+                let (du, dv) = texture.gradient(u, v);
+                Vec3(-self.strength * du, -self.strength * dv, 1.0)
+            }
+        }
+    }
+
+    // A direction the shading normal puts on the other side of the surface than the
+    // geometric normal does would let light leak through it
+    fn same_side(rec: &HitRecord, direction: &Vec3) -> bool {
+        dot(direction, &rec.normal) * dot(direction, &rec.shading_normal) > 0.0
+    }
+}
+
+impl Material for Perturbed {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.base.scatter(r_in, rec, srec) && Self::same_side(rec, srec.scattered.direction())
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn perturb(&self, r_in: &Ray, rec: &mut HitRecord) {
+        if !rec.tangent.near_zero() {
+            // The map is laid out around the outward normal
+            let side = if rec.front_face { 1.0 } else { -1.0 };
+            let frame = Onb::from_tangent(&(side * rec.shading_normal), &rec.tangent);
+            let n = side * unit_vector(&frame.transform(&self.local_normal(rec.u, rec.v)));
+            // Normals tilted away from the viewer would leave nothing to shade
+            if dot(&n, r_in.direction()) < 0.0 {
+                rec.shading_normal = n;
+            }
+        }
+        self.base.perturb(r_in, rec);
+    }
+
+    fn spectral(&self, r_in: &Ray) -> bool {
+        self.base.spectral(r_in)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if !Self::same_side(rec, direction) {
+            return Color::default();
+        }
+        self.base.eval(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if !Self::same_side(rec, direction) {
+            return 0.0;
+        }
+        self.base.pdf(r_in, rec, direction)
+    }
+}
@@ -1,17 +1,64 @@
 use crate::{
+    animation::CameraTrack,
+    aov::{self, Aov, RenderPasses},
+    aperture::Aperture,
     color::{self, Color},
     degrees_to_radians,
+    denoise::Denoiser,
+    environment::{Environment, GradientSky},
+    film::{Film, Filter},
     hittable::{HitRecord, Hittable},
-    image::ImageInfo,
+    image::{self, CropOutput, CropWindow, ImageInfo},
+    lens::LensSystem,
+    material::{Lobe, Material, ScatterRecord},
     ray::Ray,
-    vec3::{cross, random_int_unit_disk, unit_vector, Point3, Vec3},
+    sampling::power_heuristic,
+    scene::Scene,
+    spectrum::Wavelengths,
+    stereo::{Stereo, StereoMode},
+    tile::{self, Tile, TileOrder},
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
 };
 use derive_builder::Builder;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::io::{Error, Write};
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufWriter, Error, Write},
+    ops::RangeInclusive,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Sample cap of progressive renders with a noise threshold but no `max_samples` of their own
+pub const DEFAULT_MAX_SAMPLES: u32 = 4096;
 
-#[derive(Default, Debug, Clone, Copy, Builder)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic {
+        view_height: f64,
+    },
+    Equirectangular,
+    Fisheye {
+        model: FisheyeModel,
+        fov: f64,
+    },
+    // Six square faces side by side, in +u, -u, +v, -v, +w, -w order
+    Cubemap,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeModel {
+    #[default]
+    Equidistant,
+    Equisolid,
+}
+
+#[derive(Default, Debug, Clone, Builder)]
 #[builder(
     custom_constructor,
     build_fn(private, name = "try_build"),
@@ -26,6 +73,10 @@ pub struct Camera {
     samples_per_pixel: u32,
     #[builder(setter, default = "90.0")]
     fov: f64,
+    #[builder(setter, default)]
+    projection: Projection,
+    #[builder(setter(strip_option), default)]
+    lens: Option<Arc<LensSystem>>,
     #[builder(setter)]
     look_from: Point3,
     #[builder(setter)]
@@ -36,13 +87,62 @@ pub struct Camera {
     defocus_angle: f64,
     #[builder(setter, default = "10.0")]
     focus_dist: f64,
+    #[builder(setter, default)]
+    aperture: Aperture,
+    #[builder(setter, default = "1.0")]
+    anamorphic_squeeze: f64,
+    #[builder(setter, default = "0.0")]
+    cat_eye: f64,
+    #[builder(setter(strip_option), default)]
+    time_budget: Option<Duration>,
+    #[builder(setter(strip_option), default)]
+    noise_threshold: Option<f64>,
+    #[builder(setter, default = "4")]
+    pass_samples: u32,
+    // Progressive renders stop here even if neither the time budget nor the noise threshold
+    // was met. Renders with a noise threshold default to `DEFAULT_MAX_SAMPLES`, so that a
+    // threshold out of reach cannot keep them going forever.
+    #[builder(setter(strip_option), default)]
+    max_samples: Option<u32>,
+    #[builder(setter, default = "32")]
+    tile_size: u32,
+    #[builder(setter, default)]
+    tile_order: TileOrder,
+    #[builder(setter(strip_option), default)]
+    crop: Option<CropWindow>,
+    #[builder(setter, default)]
+    crop_output: CropOutput,
+    #[builder(setter(strip_option), default)]
+    stereo: Option<Stereo>,
+    #[builder(setter(strip_option), default)]
+    animation: Option<Arc<CameraTrack>>,
+    #[builder(setter, default = "0.0")]
+    frame: f64,
+    // Fraction of a frame the shutter stays open, rays are spread over [frame, frame + shutter]
+    #[builder(setter, default = "0.0")]
+    shutter: f64,
+    #[builder(setter, default)]
+    aovs: Vec<Aov>,
+    // Lights rays that leave the scene, the gradient sky when unset
+    #[builder(setter(strip_option), default)]
+    environment: Option<Arc<dyn Environment>>,
+    #[builder(setter, default)]
+    filter: Filter,
+    // In pixels
+    #[builder(setter, default = "0.5")]
+    filter_radius: f64,
+    #[builder(setter(strip_option), default)]
+    denoiser: Option<Denoiser>,
+    // Trace several wavelengths per path with RGB inputs upsampled to spectra, instead of
+    // multiplying RGB colors
+    #[builder(setter, default)]
+    spectral: bool,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
-    pixel_samples_scale: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
 }
@@ -61,6 +161,12 @@ impl CameraBuilder {
         let mut obj = self
             .try_build()
             .expect("All required fields initialized in constructorF");
+        // Faces are only square on a 6:1 image
+        assert!(
+            obj.projection != Projection::Cubemap
+                || obj.image_info.image_width == 6 * obj.image_info.image_height,
+            "Cubemap images must be six times as wide as they are high"
+        );
         obj.initialize(obj.image_info, obj.samples_per_pixel, obj.fov);
         obj
     }
@@ -72,13 +178,14 @@ impl Camera {
         self.samples_per_pixel = samples_per_pixel;
         self.fov = fov;
 
-        self.pixel_samples_scale = 1.0 / samples_per_pixel as f64;
-
         // Camera parameter
         let ray_dir = self.look_from - self.look_at;
         let theta = degrees_to_radians(fov);
         let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_height = match self.projection {
+            Projection::Orthographic { view_height } => view_height,
+            _ => 2.0 * h * self.focus_dist,
+        };
         let viewport_width =
             viewport_height * (image_info.image_width as f64 / image_info.image_height as f64);
 
@@ -97,102 +204,689 @@ impl Camera {
             self.look_from - self.focus_dist * self.w - viewport_u / 2.0 - viewport_v / 2.0;
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
+        if let Some(lens) = &self.lens {
+            self.lens = Some(Arc::new(lens.focused(self.focus_dist)));
+        }
+
         let defocus_radius = self.focus_dist * degrees_to_radians(self.defocus_angle / 2.0).tan();
         self.defocus_disk_u = defocus_radius * self.u;
         self.defocus_disk_v = defocus_radius * self.v;
     }
 
-    pub fn render(&self, file: &mut dyn Write, world: &dyn Hittable) -> Result<(), Error> {
-        let (img, samples_per_pixel, max_depth) =
-            (self.image_info, self.samples_per_pixel, self.max_depth);
-        file.write_all(format!("P3\n{} {}\n255\n", img.image_width, img.image_height).as_bytes())?;
+    pub fn render(&self, file: &mut dyn Write, scene: &Scene) -> Result<(), Error> {
+        self.render_with(file, scene, &|_, _| {})
+    }
+
+    // `on_tile` fires once per finished tile with its pixels filtered from all samples so far.
+    // Stereo renders fire it for the tiles of both eyes in turn.
+    pub fn render_with(
+        &self,
+        file: &mut dyn Write,
+        scene: &Scene,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) -> Result<(), Error> {
+        let passes = self.render_pixels(scene, on_tile);
+        let output = passes.image_info;
+
+        file.write_all(
+            format!("P3\n{} {}\n255\n", output.image_width, output.image_height).as_bytes(),
+        )?;
+        for pixel_color in passes.beauty.iter() {
+            color::write_color(file, pixel_color)?;
+        }
+
+        Ok(())
+    }
+
+    // Beauty together with the AOVs requested through `CameraBuilder::aovs`
+    pub fn render_passes(&self, scene: &Scene) -> RenderPasses {
+        self.render_pixels(scene, &|_, _| {})
+    }
+
+    // Renders each frame with the camera track applied and writes it to
+    // `directory/frame_0001.png` and so on.
+    pub fn render_sequence(
+        &self,
+        scene: &Scene,
+        frames: RangeInclusive<u32>,
+        directory: &Path,
+    ) -> Result<(), Error> {
+        for frame in frames {
+            log::info!("Rendering frame {}", frame);
+            let passes = self.at_frame(frame as f64).render_pixels(scene, &|_, _| {});
+
+            let path = directory.join(format!("frame_{:04}.png", frame));
+            let mut file = BufWriter::new(File::create(path)?);
+            image::write_png(&mut file, &passes.image_info, &passes.beauty)?;
+        }
+        Ok(())
+    }
+
+    // Camera with its animation tracks evaluated at `frame`
+    pub fn at_frame(&self, frame: f64) -> Camera {
+        let mut camera = Camera {
+            frame,
+            ..self.clone()
+        };
+        if let Some(track) = &self.animation {
+            camera.look_from = track.look_from.evaluate(frame).unwrap_or(self.look_from);
+            camera.look_at = track.look_at.evaluate(frame).unwrap_or(self.look_at);
+            camera.fov = track.fov.evaluate(frame).unwrap_or(self.fov);
+            camera.focus_dist = track.focus_dist.evaluate(frame).unwrap_or(self.focus_dist);
+            camera.initialize(camera.image_info, camera.samples_per_pixel, camera.fov);
+        }
+        camera
+    }
+
+    fn render_pixels(
+        &self,
+        scene: &Scene,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) -> RenderPasses {
+        match self.stereo {
+            Some(stereo) => {
+                let left = self.eye(&stereo, -1.0).render_image(scene, on_tile);
+                let right = self.eye(&stereo, 1.0).render_image(scene, on_tile);
+
+                let (image_info, beauty) =
+                    stereo.compose(&left.image_info, &left.beauty, &right.beauty);
+                let aovs = left
+                    .aovs
+                    .iter()
+                    .zip(right.aovs.iter())
+                    .map(|((aov, l), (_, r))| (*aov, stereo.compose(&left.image_info, l, r).1))
+                    .collect();
+                RenderPasses {
+                    image_info,
+                    beauty,
+                    aovs,
+                }
+            }
+            None => self.render_image(scene, on_tile),
+        }
+    }
+
+    fn render_image(
+        &self,
+        scene: &Scene,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) -> RenderPasses {
+        let Some(denoiser) = self.denoiser else {
+            return self.render_buffers(scene, on_tile);
+        };
+
+        // Render the feature buffers along with the beauty, then drop the ones not asked for
+        let mut camera = Camera {
+            denoiser: None,
+            ..self.clone()
+        };
+        for feature in [Aov::Albedo, Aov::Normal] {
+            if !camera.aovs.contains(&feature) {
+                camera.aovs.push(feature);
+            }
+        }
+        let mut passes = camera.render_buffers(scene, on_tile);
+        passes.denoise(&denoiser);
+        passes.aovs.retain(|(aov, _)| self.aovs.contains(aov));
+        passes
+    }
+
+    fn render_buffers(
+        &self,
+        scene: &Scene,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) -> RenderPasses {
+        let img = self.image_info;
+        let bounds = match self.crop {
+            Some(crop) => crop.bounds(&img),
+            None => Tile::full(&img),
+        };
+
+        let mut film = Film::new(&bounds, self.filter, self.filter_radius, self.aovs.len());
+        let tiles = tile::tiles(&bounds, self.tile_size, self.tile_order);
+
+        if self.time_budget.is_some() || self.noise_threshold.is_some() {
+            self.render_progressive(scene, &tiles, &mut film, on_tile);
+        } else {
+            self.render_fixed(scene, &tiles, &mut film, on_tile);
+        }
+
+        // Pixels outside the crop window were never sampled and stay black
+        let output = match self.crop_output {
+            CropOutput::Cropped => bounds,
+            CropOutput::FullFrame => Tile::full(&img),
+        };
+
+        let beauty = output.pixels().map(|p| film.pixel(p)).collect();
+        let aovs = self
+            .aovs
+            .iter()
+            .enumerate()
+            .map(|(k, aov)| (*aov, output.pixels().map(|p| film.aov(p, k)).collect()))
+            .collect();
+
+        RenderPasses {
+            image_info: ImageInfo::from_dim(output.width(), output.height()),
+            beauty,
+            aovs,
+        }
+    }
+
+    // Camera for one eye of the rig, `side` is -1 for the left eye and 1 for the right.
+    fn eye(&self, stereo: &Stereo, side: f64) -> Camera {
+        let offset = side * stereo.interocular / 2.0 * self.u;
+        let mut eye = Camera {
+            look_from: self.look_from + offset,
+            stereo: None,
+            // The eyes are rendered one after the other and share the budget
+            time_budget: self.time_budget.map(|budget| budget / 2),
+            ..self.clone()
+        };
+        eye.look_at = match stereo.mode {
+            StereoMode::ToeIn => self.look_from - stereo.convergence * self.w,
+            StereoMode::OffAxis => self.look_at + offset,
+        };
+        eye.initialize(eye.image_info, eye.samples_per_pixel, eye.fov);
+
+        // Shift the viewport so that the convergence plane has zero parallax
+        if stereo.mode == StereoMode::OffAxis && self.projection == Projection::Perspective {
+            eye.pixel00_loc -=
+                side * stereo.interocular / 2.0 * self.focus_dist / stereo.convergence * self.u;
+        }
+        eye
+    }
 
+    fn render_fixed(
+        &self,
+        scene: &Scene,
+        tiles: &[Tile],
+        film: &mut Film,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) {
         let render_bar = {
             let style = ProgressStyle::with_template(
                 "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg} ({per_sec})",
             )
             .unwrap()
             .progress_chars("#>.");
-            let bar = ProgressBar::new(img.image_height as u64);
+            let bar = ProgressBar::new(tiles.len() as u64);
             bar.set_style(style);
             bar
         };
 
-        let mut image: Box<[Vec3]> =
-            vec![Vec3::default(); (img.image_height * img.image_width) as usize].into_boxed_slice();
+        self.render_pass(
+            scene,
+            tiles,
+            self.samples_per_pixel,
+            film,
+            &render_bar,
+            on_tile,
+        );
+        render_bar.finish();
+    }
 
-        image
-            .par_chunks_mut(img.image_width as usize)
-            .enumerate()
-            .for_each(|(j, row)| {
-                row.par_iter_mut().enumerate().for_each(|(i, pixel)| {
-                    let (i, j) = (i as f64, j as f64);
-                    let pixel_color = (0..samples_per_pixel)
-                        .into_iter()
-                        .map(|_| {
-                            let r = self.get_ray(i, j);
-                            Camera::ray_color(&r, max_depth, world)
-                        })
-                        .reduce(|acc, c| acc + c).unwrap();
-                    *pixel = pixel_color;
-                });
-                render_bar.inc(1);
-            });
-
-        for pixel_color in image {
-            color::write_color(file, &(self.pixel_samples_scale * pixel_color))?;
+    // Runs passes of `pass_samples` until the time budget would be exceeded by another pass,
+    // the noise level drops below the threshold or the sample cap is reached, whichever comes
+    // first.
+    fn render_progressive(
+        &self,
+        scene: &Scene,
+        tiles: &[Tile],
+        film: &mut Film,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) {
+        let render_bar = {
+            let style =
+                ProgressStyle::with_template("[{elapsed_precise}] {spinner} {msg}").unwrap();
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(style);
+            bar
+        };
+
+        let start = Instant::now();
+        let pass_samples = self.pass_samples.max(1);
+        let max_samples = self
+            .max_samples
+            .or(self.noise_threshold.map(|_| DEFAULT_MAX_SAMPLES));
+        let mut samples = 0;
+        loop {
+            let pass_start = Instant::now();
+            samples += pass_samples;
+            self.render_pass(
+                scene,
+                tiles,
+                pass_samples,
+                film,
+                &ProgressBar::hidden(),
+                on_tile,
+            );
+
+            let noise = film.noise_level(samples);
+            render_bar.set_message(format!("{} spp, noise {:.4}", samples, noise));
+            render_bar.tick();
+
+            let out_of_time = self
+                .time_budget
+                .is_some_and(|budget| start.elapsed() + pass_start.elapsed() > budget);
+            let converged = self.noise_threshold.is_some_and(|target| noise <= target);
+            let limit = if converged {
+                Some("noise threshold reached")
+            } else if out_of_time {
+                Some("time budget spent")
+            } else if max_samples.is_some_and(|max| samples >= max) {
+                Some("sample cap reached")
+            } else {
+                None
+            };
+            if let Some(limit) = limit {
+                log::info!(
+                    "Progressive render stopped after {} spp with noise {:.4} in {:.2?}: {}",
+                    samples,
+                    noise,
+                    start.elapsed(),
+                    limit
+                );
+                break;
+            }
         }
 
         render_bar.finish();
-        Ok(())
+    }
+
+    fn render_pass(
+        &self,
+        scene: &Scene,
+        tiles: &[Tile],
+        samples: u32,
+        film: &mut Film,
+        render_bar: &ProgressBar,
+        on_tile: &(dyn Fn(&Tile, &[Color]) + Sync),
+    ) {
+        let aov_count = self.aovs.len();
+        let film = Mutex::new(film);
+
+        // `par_bridge` pulls tiles in order, so the scheduling order is preserved
+        tiles.iter().par_bridge().for_each(|tile| {
+            // Samples near the tile edges splat into neighbouring tiles, so each tile
+            // accumulates into its own film and merges it once done
+            let mut tile_film = film.lock().unwrap().tile(tile);
+            let mut aovs = vec![Color::default(); aov_count];
+            for (i, j) in tile.pixels() {
+                for _ in 0..samples {
+                    aovs.fill(Color::default());
+                    let offset = Camera::sample_square();
+                    let sample = match self.get_ray(i as f64, j as f64, &offset) {
+                        Some((r, weight)) => {
+                            let r = r.with_wavelengths(self.spectral.then(Wavelengths::sample));
+                            if aov_count > 0 {
+                                self.ray_color_aovs(&r, weight, scene, &mut aovs)
+                            } else {
+                                let color = self.ray_color(&r, self.max_depth, scene, 0.0);
+                                weight * self.to_rgb(&color, &r)
+                            }
+                        }
+                        None => Color::default(),
+                    };
+                    let position = (i as f64 + 0.5 + offset.x(), j as f64 + 0.5 + offset.y());
+                    tile_film.add_sample((i, j), position, &sample, &aovs);
+                }
+            }
+
+            let preview: Vec<Color> = {
+                let mut film = film.lock().unwrap();
+                film.merge(&tile_film);
+                tile.pixels().map(|p| film.pixel(p)).collect()
+            };
+
+            on_tile(tile, &preview);
+            render_bar.inc(1);
+        });
     }
 
     fn sample_square() -> Vec3 {
         Vec3(fastrand::f64() - 0.5, fastrand::f64() - 0.5, 0.0)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_int_unit_disk();
-        self.look_from + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
+    // `film` is the sample position on the image plane, scaled so that the corners are at
+    // distance 1 from the center. Returns `None` when the sample is vignetted.
+    fn defocus_disk_sample(&self, center: &Point3, film: (f64, f64)) -> Option<Point3> {
+        let p = self.aperture.sample();
+
+        // Cat-eye vignetting clips the aperture with a second pupil that slides off-center
+        // towards the frame edges
+        if self.cat_eye > 0.0 {
+            let pupil = Vec3(self.cat_eye * film.0, self.cat_eye * film.1, 0.0);
+            if (p - pupil).length_squared() > 1.0 {
+                return None;
+            }
+        }
+
+        let p = Vec3(p.x() / self.anamorphic_squeeze, p.y(), 0.0);
+        Some(center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v)
     }
 
-    fn get_ray(&self, i: f64, j: f64) -> Ray {
-        let offset = Camera::sample_square();
+    // `offset` is the sample position relative to the center of pixel (i, j)
+    fn get_ray(&self, i: f64, j: f64, offset: &Vec3) -> Option<(Ray, f64)> {
+        let time = self.frame + self.shutter * fastrand::f64();
+
+        if let Some(lens) = &self.lens {
+            let (width, height) = (
+                self.image_info.image_width as f64,
+                self.image_info.image_height as f64,
+            );
+            let (film_width, film_height) = lens.film_size(self.image_info.aspect_ratio());
+            // The lens flips the image, so the film is sampled mirrored in x and y
+            let film = Point3(
+                -((i + 0.5 + offset.x()) / width - 0.5) * film_width,
+                ((j + 0.5 + offset.y()) / height - 0.5) * film_height,
+                0.0,
+            );
+            let (r, weight) = lens.generate_ray(&film, fastrand::f64(), fastrand::f64())?;
+            let to_world = |p: &Vec3| p.x() * self.u + p.y() * self.v + p.z() * self.w;
+            return Some((
+                Ray::with_time(
+                    &(self.look_from + to_world(r.origin())),
+                    &to_world(r.direction()),
+                    time,
+                ),
+                weight,
+            ));
+        }
+
+        if !matches!(
+            self.projection,
+            Projection::Perspective | Projection::Orthographic { .. }
+        ) {
+            let direction = self.panoramic_direction(i + 0.5 + offset.x(), j + 0.5 + offset.y())?;
+            return Some((Ray::with_time(&self.look_from, &direction, time), 1.0));
+        }
+
         let pixel_sample = self.pixel00_loc
             + ((i + offset.x()) * self.pixel_delta_u)
             + ((j + offset.y()) * self.pixel_delta_v);
 
+        // Orthographic rays start on the camera plane directly behind their pixel, parallel to `w`
+        let lens_center = match self.projection {
+            Projection::Orthographic { .. } => pixel_sample + self.focus_dist * self.w,
+            _ => self.look_from,
+        };
         let ray_origin = if self.defocus_angle > 0.0 {
-            self.defocus_disk_sample()
+            let aspect_ratio = self.image_info.aspect_ratio();
+            let half_diagonal = (1.0 + aspect_ratio * aspect_ratio).sqrt();
+            let film = (
+                (2.0 * (i + 0.5 + offset.x()) / self.image_info.image_width as f64 - 1.0)
+                    * aspect_ratio
+                    / half_diagonal,
+                (1.0 - 2.0 * (j + 0.5 + offset.y()) / self.image_info.image_height as f64)
+                    / half_diagonal,
+            );
+            self.defocus_disk_sample(&lens_center, film)?
         } else {
-            self.look_from
+            lens_center
         };
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(&ray_origin, &ray_direction)
+        Some((Ray::with_time(&ray_origin, &ray_direction, time), 1.0))
+    }
+
+    // Maps a continuous pixel position to a view direction in the `u`, `v`, `w` basis.
+    // Returns `None` for pixels the projection does not cover.
+    fn panoramic_direction(&self, x: f64, y: f64) -> Option<Vec3> {
+        let (width, height) = (
+            self.image_info.image_width as f64,
+            self.image_info.image_height as f64,
+        );
+        let (u, v, w) = (self.u, self.v, self.w);
+
+        match self.projection {
+            Projection::Equirectangular => {
+                let longitude = (x / width - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y / height) * PI;
+                Some(
+                    latitude.cos() * longitude.sin() * u + latitude.sin() * v
+                        - latitude.cos() * longitude.cos() * w,
+                )
+            }
+            Projection::Fisheye { model, fov } => {
+                let radius = width.min(height) / 2.0;
+                let (px, py) = ((x - width / 2.0) / radius, (height / 2.0 - y) / radius);
+                let r = (px * px + py * py).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half_fov = degrees_to_radians(fov) / 2.0;
+                let theta = match model {
+                    FisheyeModel::Equidistant => r * half_fov,
+                    FisheyeModel::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let (cos_phi, sin_phi) = if r > 0.0 {
+                    (px / r, py / r)
+                } else {
+                    (1.0, 0.0)
+                };
+                Some(theta.sin() * (cos_phi * u + sin_phi * v) - theta.cos() * w)
+            }
+            Projection::Cubemap => {
+                let face_size = width / 6.0;
+                let face = ((x / face_size) as usize).min(5);
+                let a = 2.0 * (x - face as f64 * face_size) / face_size - 1.0;
+                let b = 1.0 - 2.0 * y / height;
+                let (forward, right, up) = match face {
+                    0 => (u, w, v),
+                    1 => (-u, -w, v),
+                    2 => (v, u, w),
+                    3 => (-v, u, -w),
+                    4 => (w, -u, v),
+                    _ => (-w, u, v),
+                };
+                Some(forward + a * right + b * up)
+            }
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+        }
+    }
+
+    // `bsdf_pdf` is the density the previous bounce sampled `r` with, zero for camera rays and
+    // lobes that light sampling cannot reach. It weights the environment seen along `r`
+    // against the light samples taken at that bounce.
+    fn ray_color(&self, r: &Ray, depth: u32, scene: &Scene, bsdf_pdf: f64) -> Color {
+        let hit = self.trace(r, depth, scene, bsdf_pdf);
+        hit.emitted + hit.scattered
     }
 
-    fn ray_color(r: &Ray, depth: u32, world: &dyn Hittable) -> Color {
+    // Radiance along `r` like `ray_color`, split up at the first hit for the AOVs
+    fn trace(&self, r: &Ray, depth: u32, scene: &Scene, bsdf_pdf: f64) -> PathHit {
         if depth == 0 {
-            return Color::default();
+            return PathHit::default();
         }
 
         let mut rec = HitRecord::default();
-        if world.hit(r, 0.001..=f64::INFINITY, &mut rec) {
-            let (mut attenuation, mut scattered) = (Color::default(), Ray::default());
-            if rec
-                .mat
-                .clone()
-                .unwrap()
-                .scatter(r, &rec, &mut attenuation, &mut scattered)
-            {
-                return attenuation * Camera::ray_color(&scattered, depth - 1, world);
+        if !scene.world().hit(r, 0.001..=f64::INFINITY, &mut rec) {
+            return PathHit {
+                emitted: self.environment_radiance(r, bsdf_pdf),
+                ..Default::default()
+            };
+        }
+
+        let mat = rec.mat.clone().unwrap();
+        mat.perturb(r, &mut rec);
+        let emitted = self.material_spectrum(mat.as_ref(), &mat.emitted(r, &rec), r);
+        // Light sampling does not depend on the BSDF sample, so it counts even when that fails
+        let direct = self.sample_lights(r, &rec, scene);
+        let mut srec = ScatterRecord::default();
+        if !mat.scatter(r, &rec, &mut srec) {
+            return PathHit {
+                rec: Some(rec),
+                emitted,
+                scattered: direct,
+                srec: None,
+            };
+        }
+
+        let (scattered, attenuation) = self.continue_path(r, mat.as_ref(), &srec);
+        PathHit {
+            scattered: direct
+                + attenuation * self.ray_color(&scattered, depth - 1, scene, srec.pdf),
+            rec: Some(rec),
+            emitted,
+            srec: Some(srec),
+        }
+    }
+
+    fn environment(&self) -> &dyn Environment {
+        self.environment.as_deref().unwrap_or(&GradientSky)
+    }
+
+    // `color` as seen along `r`, sampled at the path's wavelengths in spectral mode
+    fn spectrum(&self, color: &Color, r: &Ray) -> Color {
+        match r.wavelengths() {
+            Some(wavelengths) if self.spectral => wavelengths.sample_rgb(color),
+            _ => *color,
+        }
+    }
+
+    // Radiance traced along the camera ray `r` back to RGB
+    fn to_rgb(&self, radiance: &Color, r: &Ray) -> Color {
+        match r.wavelengths() {
+            Some(wavelengths) if self.spectral => wavelengths.to_rgb(radiance),
+            _ => *radiance,
+        }
+    }
+
+    // Like `spectrum` for a color `mat` returned, which it may have sampled at the path's
+    // wavelengths itself
+    fn material_spectrum(&self, mat: &dyn Material, color: &Color, r: &Ray) -> Color {
+        if mat.spectral(r) {
+            *color
+        } else {
+            self.spectrum(color, r)
+        }
+    }
+
+    // The ray the path goes on with after `r` scattered off `mat` as `srec`, and its
+    // attenuation
+    fn continue_path(&self, r: &Ray, mat: &dyn Material, srec: &ScatterRecord) -> (Ray, Color) {
+        // Materials that ignore wavelengths keep the ones the path already carries
+        let scattered = srec
+            .scattered
+            .with_wavelengths(srec.scattered.wavelengths().or(r.wavelengths()));
+        let mut attenuation = self.material_spectrum(mat, &srec.attenuation, r);
+        // Only the hero follows a dispersive bounce, and stands in for all three wavelengths
+        if let (Some(Wavelengths::Hero(_)), Some(Wavelengths::Single(_))) =
+            (r.wavelengths(), scattered.wavelengths())
+        {
+            attenuation = attenuation * Color(3.0, 0.0, 0.0);
+        }
+        (scattered, attenuation)
+    }
+
+    fn environment_radiance(&self, r: &Ray, bsdf_pdf: f64) -> Color {
+        let environment = self.environment();
+        let radiance = self.spectrum(&environment.radiance(r.direction()), r);
+        if bsdf_pdf == 0.0 {
+            return radiance;
+        }
+        power_heuristic(bsdf_pdf, environment.pdf(r.direction())) * radiance
+    }
+
+    // Next event estimation at `rec`. The environment is weighted against the BSDF sample with
+    // multiple importance sampling, punctual lights can only be reached from here. This runs
+    // even when the BSDF sample took a delta lobe, as layered materials can have smooth lobes
+    // besides it that only `eval` sees.
+    fn sample_lights(&self, r: &Ray, rec: &HitRecord, scene: &Scene) -> Color {
+        let mat = rec.mat.as_ref().unwrap();
+        let unoccluded = |direction: &Vec3, distance: f64| {
+            let shadow = Ray::with_time(&rec.p, direction, r.time());
+            !scene
+                .world()
+                .hit(&shadow, 0.001..=distance, &mut HitRecord::default())
+        };
+
+        let mut color = Color::default();
+        if let Some((direction, radiance, light_pdf)) = self.environment().sample() {
+            let f = mat.eval(r, rec, &direction);
+            if !f.near_zero() && unoccluded(&direction, f64::INFINITY) {
+                let weight = power_heuristic(light_pdf, mat.pdf(r, rec, &direction));
+                color += (weight / light_pdf)
+                    * self.material_spectrum(mat.as_ref(), &f, r)
+                    * self.spectrum(&radiance, r);
+            }
+        }
+
+        for light in scene.lights().lights() {
+            let Some(sample) = light.sample(&rec.p) else {
+                continue;
+            };
+            let f = mat.eval(r, rec, &sample.direction);
+            if !f.near_zero() && unoccluded(&sample.direction, sample.distance) {
+                color += self.material_spectrum(mat.as_ref(), &f, r)
+                    * self.spectrum(&sample.radiance, r);
+            }
+        }
+        color
+    }
+
+    // Traces a camera ray like `ray_color` and adds its first-hit AOVs to `aovs`, ordered
+    // as `self.aovs`. `weight` scales the radiance passes only.
+    fn ray_color_aovs(&self, r: &Ray, weight: f64, scene: &Scene, aovs: &mut [Color]) -> Color {
+        let mut values = [Color::default(); Aov::ALL.len()];
+        let mut set = |aov: Aov, value: Color| values[aov as usize] = value;
+
+        let hit = self.trace(r, self.max_depth, scene, 0.0);
+        let emitted = weight * self.to_rgb(&hit.emitted, r);
+        let scattered = weight * self.to_rgb(&hit.scattered, r);
+        // Rays that leave the scene show the environment in the emission pass
+        set(Aov::Emission, emitted);
+
+        if let Some(rec) = &hit.rec {
+            let depth = dot(&(rec.p - self.look_from), &-self.w);
+            set(Aov::Normal, rec.shading_normal);
+            set(Aov::Depth, Color(depth, depth, depth));
+            set(Aov::Position, rec.p);
+            set(Aov::Uv, Color(rec.u, rec.v, 0.0));
+            if let Some(mat) = &rec.mat {
+                set(
+                    Aov::MaterialId,
+                    aov::id_color(scene.material_id(mat) as u64),
+                );
             }
-            return Color::default();
+            set(Aov::ObjectId, aov::id_color(rec.object_id as u64));
         }
 
-        let unit_direction = unit_vector(r.direction());
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color(1.0, 1.0, 1.0) + a * Color(0.5, 0.7, 1.0)
+        if let (Some(srec), Some(mat)) =
+            (&hit.srec, hit.rec.as_ref().and_then(|rec| rec.mat.as_ref()))
+        {
+            // Spectral materials give the albedo at the path's wavelengths, which the denoiser
+            // needs as RGB like everything else
+            let albedo = if mat.spectral(r) {
+                self.to_rgb(&srec.attenuation, r)
+            } else {
+                srec.attenuation
+            };
+            set(Aov::Albedo, albedo);
+        }
+        // Direct light at a hit whose BSDF sample failed has no lobe to go with, and counts as
+        // diffuse
+        let lobe = match hit.srec.map_or(Lobe::Diffuse, |srec| srec.lobe) {
+            Lobe::Diffuse => Aov::Diffuse,
+            Lobe::Specular => Aov::Specular,
+            Lobe::Transmission => Aov::Transmission,
+        };
+        set(lobe, scattered);
+
+        for (acc, aov) in aovs.iter_mut().zip(self.aovs.iter()) {
+            *acc += values[*aov as usize];
+        }
+        emitted + scattered
     }
 }
+
+// What a path found at its first hit, with the radiance it carries back split into light
+// emitted there and light scattered there
+#[derive(Default)]
+struct PathHit {
+    // `None` when the ray left the scene, with the environment in `emitted`
+    rec: Option<HitRecord>,
+    srec: Option<ScatterRecord>,
+    emitted: Color,
+    scattered: Color,
+}
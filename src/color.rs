@@ -18,6 +18,10 @@ impl Mul for Color {
     }
 }
 
+pub fn luminance(color: &Color) -> f64 {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
 fn linear_to_gamma(linear_component: f64) -> f64 {
     if linear_component > 0.0 {
         return linear_component.sqrt();
@@ -25,17 +29,21 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     0.0
 }
 
-pub fn write_color(file: &mut dyn Write, pixel_color: &Color) -> Result<(), Error> {
+pub fn to_rgb8(pixel_color: &Color) -> [u8; 3] {
     let (r, g, b) = pixel_color.into();
 
     let (r, g, b) = (linear_to_gamma(r), linear_to_gamma(g), linear_to_gamma(b));
 
     let (intensity_min, intensity_max) = (0.0, 0.999);
-    let (rbyte, gbyte, bbyte) = (
-        (256.0 * r.clamp(intensity_min, intensity_max)) as i32,
-        (256.0 * g.clamp(intensity_min, intensity_max)) as i32,
-        (256.0 * b.clamp(intensity_min, intensity_max)) as i32,
-    );
+    [
+        (256.0 * r.clamp(intensity_min, intensity_max)) as u8,
+        (256.0 * g.clamp(intensity_min, intensity_max)) as u8,
+        (256.0 * b.clamp(intensity_min, intensity_max)) as u8,
+    ]
+}
+
+pub fn write_color(file: &mut dyn Write, pixel_color: &Color) -> Result<(), Error> {
+    let [rbyte, gbyte, bbyte] = to_rgb8(pixel_color);
 
     file.write_all(format!("{} {} {}\n", rbyte, gbyte, bbyte).as_bytes())?;
 
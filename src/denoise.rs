@@ -0,0 +1,218 @@
+use crate::{color::Color, image::ImageInfo};
+use rayon::prelude::*;
+
+// B3-spline taps of the À-Trous kernel, spread 2^i pixels apart on iteration i
+const ATROUS_KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Edge-stopping scales for the feature buffers, colors are compared after tone mapping
+const COLOR_SIGMA: f64 = 0.2;
+const NLM_SIGMA: f64 = 0.04;
+const ALBEDO_SIGMA: f64 = 0.1;
+const NORMAL_SIGMA: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenoiseFilter {
+    // Edge-avoiding À-Trous wavelet filter (Dammertz et al. 2010)
+    ATrous {
+        iterations: u32,
+    },
+    // Non-local means over `patch_radius` patches within a `search_radius` window
+    NonLocalMeans {
+        search_radius: u32,
+        patch_radius: u32,
+    },
+}
+
+// Image-space filter for noisy renders. `strength` scales how different two colors may be
+// and still get blended, the albedo and normal buffers keep the filter from blurring across
+// texture and geometry edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    pub filter: DenoiseFilter,
+    pub strength: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            filter: DenoiseFilter::ATrous { iterations: 5 },
+            strength: 1.0,
+        }
+    }
+}
+
+struct Features<'a> {
+    width: usize,
+    height: usize,
+    albedo: Option<&'a [Color]>,
+    normal: Option<&'a [Color]>,
+}
+
+impl Features<'_> {
+    fn weight(&self, p: usize, q: usize) -> f64 {
+        let albedo = self.albedo.map_or(1.0, |albedo| {
+            (-(albedo[p] - albedo[q]).length_squared() / (ALBEDO_SIGMA * ALBEDO_SIGMA)).exp()
+        });
+        let normal = self.normal.map_or(1.0, |normal| {
+            (-(normal[p] - normal[q]).length_squared() / (NORMAL_SIGMA * NORMAL_SIGMA)).exp()
+        });
+        albedo * normal
+    }
+
+    // Neighbour of pixel (x, y) at offset (dx, dy), or None past the image border
+    fn offset(&self, x: usize, y: usize, dx: i64, dy: i64) -> Option<usize> {
+        let (qx, qy) = (x as i64 + dx, y as i64 + dy);
+        if qx < 0 || qy < 0 || qx >= self.width as i64 || qy >= self.height as i64 {
+            return None;
+        }
+        Some(qy as usize * self.width + qx as usize)
+    }
+}
+
+impl Denoiser {
+    // All buffers hold one value per pixel of `image_info` in row-major order. Misses are
+    // expected to carry a black albedo and a zero normal, as the AOV passes do.
+    pub fn denoise(
+        &self,
+        image_info: &ImageInfo,
+        beauty: &[Color],
+        albedo: Option<&[Color]>,
+        normal: Option<&[Color]>,
+    ) -> Vec<Color> {
+        let features = Features {
+            width: image_info.image_width as usize,
+            height: image_info.image_height as usize,
+            albedo,
+            normal,
+        };
+
+        // Filter the irradiance rather than the beauty so textures stay sharp
+        let modulation: Vec<Color> = match albedo {
+            Some(albedo) => albedo
+                .iter()
+                .map(|a| {
+                    let demodulate = |c: f64| if c > 1e-3 { c } else { 1.0 };
+                    Color(demodulate(a.x()), demodulate(a.y()), demodulate(a.z()))
+                })
+                .collect(),
+            None => vec![Color(1.0, 1.0, 1.0); beauty.len()],
+        };
+        let irradiance: Vec<Color> = beauty
+            .iter()
+            .zip(modulation.iter())
+            .map(|(c, m)| Color(c.x() / m.x(), c.y() / m.y(), c.z() / m.z()))
+            .collect();
+
+        let filtered = match self.filter {
+            DenoiseFilter::ATrous { iterations } => self.atrous(&features, irradiance, iterations),
+            DenoiseFilter::NonLocalMeans {
+                search_radius,
+                patch_radius,
+            } => self.non_local_means(&features, &irradiance, search_radius, patch_radius),
+        };
+
+        filtered
+            .iter()
+            .zip(modulation.iter())
+            .map(|(c, m)| *c * *m)
+            .collect()
+    }
+
+    fn atrous(&self, features: &Features, mut color: Vec<Color>, iterations: u32) -> Vec<Color> {
+        let mut sigma = self.strength * COLOR_SIGMA;
+        for i in 0..iterations {
+            let step = 1i64 << i;
+            let guide: Vec<Color> = color.iter().map(tone_map).collect();
+
+            let mut filtered = vec![Color::default(); color.len()];
+            filtered
+                .par_chunks_mut(features.width)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    for (x, out) in row.iter_mut().enumerate() {
+                        let p = y * features.width + x;
+                        let (mut sum, mut weight_sum) = (Color::default(), 0.0);
+                        for (ky, hy) in ATROUS_KERNEL.iter().enumerate() {
+                            for (kx, hx) in ATROUS_KERNEL.iter().enumerate() {
+                                let (dx, dy) = ((kx as i64 - 2) * step, (ky as i64 - 2) * step);
+                                let Some(q) = features.offset(x, y, dx, dy) else {
+                                    continue;
+                                };
+                                let color_weight = (-(guide[p] - guide[q]).length_squared()
+                                    / (sigma * sigma).max(1e-12))
+                                .exp();
+                                let weight = hx * hy * color_weight * features.weight(p, q);
+                                sum += weight * color[q];
+                                weight_sum += weight;
+                            }
+                        }
+                        *out = sum / weight_sum;
+                    }
+                });
+
+            color = filtered;
+            // Coarser levels hold less noise, so tighten the color test as the taps spread
+            sigma *= 0.5;
+        }
+        color
+    }
+
+    fn non_local_means(
+        &self,
+        features: &Features,
+        color: &[Color],
+        search_radius: u32,
+        patch_radius: u32,
+    ) -> Vec<Color> {
+        let guide: Vec<Color> = color.iter().map(tone_map).collect();
+        let h = self.strength * NLM_SIGMA;
+        let (search, patch) = (search_radius as i64, patch_radius as i64);
+
+        let mut filtered = vec![Color::default(); color.len()];
+        filtered
+            .par_chunks_mut(features.width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    let p = y * features.width + x;
+                    let (mut sum, mut weight_sum) = (Color::default(), 0.0);
+                    for dy in -search..=search {
+                        for dx in -search..=search {
+                            let Some(q) = features.offset(x, y, dx, dy) else {
+                                continue;
+                            };
+                            let (qx, qy) = (q % features.width, q / features.width);
+
+                            // Mean squared difference of the patches around p and q
+                            let (mut distance, mut count) = (0.0, 0);
+                            for py in -patch..=patch {
+                                for px in -patch..=patch {
+                                    if let (Some(a), Some(b)) = (
+                                        features.offset(x, y, px, py),
+                                        features.offset(qx, qy, px, py),
+                                    ) {
+                                        distance += (guide[a] - guide[b]).length_squared() / 3.0;
+                                        count += 1;
+                                    }
+                                }
+                            }
+                            let distance = distance / count.max(1) as f64;
+
+                            let weight =
+                                (-distance / (h * h).max(1e-12)).exp() * features.weight(p, q);
+                            sum += weight * color[q];
+                            weight_sum += weight;
+                        }
+                    }
+                    *out = sum / weight_sum;
+                }
+            });
+        filtered
+    }
+}
+
+// Compresses highlights so fireflies do not dominate the color distances
+fn tone_map(color: &Color) -> Color {
+    let map = |c: f64| c.max(0.0) / (1.0 + c.max(0.0));
+    Color(map(color.x()), map(color.y()), map(color.z()))
+}
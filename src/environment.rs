@@ -0,0 +1,176 @@
+use crate::{
+    color::{luminance, Color},
+    degrees_to_radians,
+    image::{self, ImageInfo},
+    sampling::Distribution2D,
+    vec3::{unit_vector, Vec3},
+};
+use std::{
+    f64::consts::PI,
+    fmt::Debug,
+    fs::File,
+    io::{BufReader, Error, ErrorKind},
+    path::Path,
+};
+
+// Radiance arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment: Debug + Send + Sync {
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    // Picks a direction to gather light from, returned with its radiance and solid angle
+    // density. Environments that cannot be importance sampled return `None`.
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        None
+    }
+
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+// White at the horizon fading to blue overhead
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GradientSky;
+
+impl Environment for GradientSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let unit_direction = unit_vector(direction);
+        let a = 0.5 * (unit_direction.y() + 1.0);
+        (1.0 - a) * Color(1.0, 1.0, 1.0) + a * Color(0.5, 0.7, 1.0)
+    }
+}
+
+// Equirectangular map with +y at the top row and -z at the center column, turned about the
+// y axis by `rotation` degrees. Directions are sampled proportionally to luminance.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    image_info: ImageInfo,
+    pixels: Vec<Color>,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image_info: &ImageInfo, pixels: Vec<Color>) -> Self {
+        let (width, height) = (
+            image_info.image_width as usize,
+            image_info.image_height as usize,
+        );
+        // Rows near the poles cover less solid angle
+        let func: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(k, pixel)| {
+                let theta = PI * ((k / width) as f64 + 0.5) / height as f64;
+                luminance(pixel) * theta.sin()
+            })
+            .collect();
+
+        Self {
+            image_info: *image_info,
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+            distribution: Distribution2D::new(&func, width, height),
+        }
+    }
+
+    // Loads a Radiance `.hdr` or a PFM file, picked by extension
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let extension = path.extension().and_then(|e| e.to_str());
+        let (image_info, pixels) = match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("hdr") => image::read_hdr(&mut reader)?,
+            Some("pfm") => image::read_pfm(&mut reader)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Environment maps must be .hdr or .pfm files",
+                ))
+            }
+        };
+        Ok(Self::new(&image_info, pixels))
+    }
+
+    // Tabulates `radiance` at the pixel centers of a map of the given size
+    pub fn bake(image_info: &ImageInfo, radiance: impl Fn(&Vec3) -> Color) -> Self {
+        let (width, height) = (image_info.image_width, image_info.image_height);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                radiance(&from_uv(
+                    (x as f64 + 0.5) / width as f64,
+                    (y as f64 + 0.5) / height as f64,
+                ))
+            })
+            .collect();
+        Self::new(image_info, pixels)
+    }
+
+    // Luminance integrated over all directions
+    pub fn power(&self) -> f64 {
+        2.0 * PI * PI * self.intensity * self.distribution.integral()
+    }
+
+    pub fn rotation(self, rotation: f64) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn intensity(self, intensity: f64) -> Self {
+        Self { intensity, ..self }
+    }
+
+    fn to_uv(&self, direction: &Vec3) -> (f64, f64) {
+        let d = unit_vector(direction);
+        let phi = d.x().atan2(-d.z()) - degrees_to_radians(self.rotation);
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn lookup(&self, (u, v): (f64, f64)) -> Color {
+        let (width, height) = (self.image_info.image_width, self.image_info.image_height);
+        let x = ((u * width as f64) as u32).min(width - 1);
+        let y = ((v * height as f64) as u32).min(height - 1);
+        self.intensity * self.pixels[(y * width + x) as usize]
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        self.lookup(self.to_uv(direction))
+    }
+
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        let ((u, v), pdf) = self.distribution.sample(fastrand::f64(), fastrand::f64());
+        let sin_theta = (PI * v).sin();
+        if pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        let direction = from_uv(u + self.rotation / 360.0, v);
+        // Converts the density over the map to one over solid angle
+        let pdf = pdf / (2.0 * PI * PI * sin_theta);
+        Some((direction, self.lookup((u, v)), pdf))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// Direction at map coordinates (u, v) of an unrotated map
+fn from_uv(u: f64, v: f64) -> Vec3 {
+    let (theta, phi) = (PI * v, 2.0 * PI * (u - 0.5));
+    Vec3(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
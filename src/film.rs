@@ -0,0 +1,234 @@
+use crate::{
+    color::{luminance, Color},
+    tile::Tile,
+};
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+// Pixel reconstruction filter, evaluated separably in x and y. The radius is given in pixels
+// alongside the filter, a box of radius 0.5 keeps every sample in its own pixel.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    #[default]
+    Box,
+    Tent,
+    // Shifted down so that it reaches zero at the radius
+    Gaussian {
+        sigma: f64,
+    },
+    // Mitchell-Netravali cubic, B = C = 1/3 is the usual choice
+    Mitchell {
+        b: f64,
+        c: f64,
+    },
+    // Sinc windowed by a sinc stretched over the radius
+    Lanczos,
+}
+
+impl Filter {
+    pub fn evaluate(&self, x: f64, y: f64, radius: f64) -> f64 {
+        self.evaluate_1d(x, radius) * self.evaluate_1d(y, radius)
+    }
+
+    fn evaluate_1d(&self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x >= radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - x,
+            Filter::Gaussian { sigma } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { b, c } => {
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct FilmPixel {
+    sum: Color,
+    weight_sum: f64,
+    // Unfiltered luminance of the samples taken in this pixel, for the noise estimate
+    luminance_sum: f64,
+    luminance_sq_sum: f64,
+}
+
+// Accumulates filtered samples over `bounds`. Every sample is splatted to the pixels within
+// the filter radius of its position, and each pixel is normalized by its total filter weight.
+pub struct Film {
+    bounds: Tile,
+    filter: Filter,
+    radius: f64,
+    pixels: Box<[FilmPixel]>,
+    // `aov_count` weighted sums per pixel, filtered like the beauty
+    aovs: Box<[Color]>,
+    aov_count: usize,
+}
+
+impl Film {
+    pub fn new(bounds: &Tile, filter: Filter, radius: f64, aov_count: usize) -> Self {
+        let pixel_count = (bounds.width() * bounds.height()) as usize;
+        Self {
+            bounds: *bounds,
+            filter,
+            radius,
+            pixels: vec![FilmPixel::default(); pixel_count].into_boxed_slice(),
+            aovs: vec![Color::default(); pixel_count * aov_count].into_boxed_slice(),
+            aov_count,
+        }
+    }
+
+    // Empty film covering every pixel that samples taken in `tile` can reach
+    pub fn tile(&self, tile: &Tile) -> Film {
+        let margin = (self.radius - 0.5).max(0.0).ceil() as u32;
+        let bounds = Tile {
+            x0: tile.x0.saturating_sub(margin).max(self.bounds.x0),
+            y0: tile.y0.saturating_sub(margin).max(self.bounds.y0),
+            x1: (tile.x1 + margin).min(self.bounds.x1),
+            y1: (tile.y1 + margin).min(self.bounds.y1),
+        };
+        Film::new(&bounds, self.filter, self.radius, self.aov_count)
+    }
+
+    pub fn bounds(&self) -> Tile {
+        self.bounds
+    }
+
+    fn index(&self, (i, j): (u32, u32)) -> Option<usize> {
+        let b = &self.bounds;
+        if i < b.x0 || i >= b.x1 || j < b.y0 || j >= b.y1 {
+            return None;
+        }
+        Some(((j - b.y0) * b.width() + (i - b.x0)) as usize)
+    }
+
+    // `pixel` is the pixel the sample was taken in and `position` its continuous image
+    // position, with pixel (i, j) covering [i, i + 1) x [j, j + 1)
+    pub fn add_sample(
+        &mut self,
+        pixel: (u32, u32),
+        position: (f64, f64),
+        color: &Color,
+        aovs: &[Color],
+    ) {
+        if let Some(index) = self.index(pixel) {
+            let luminance = luminance(color);
+            self.pixels[index].luminance_sum += luminance;
+            self.pixels[index].luminance_sq_sum += luminance * luminance;
+        }
+
+        // Pixels whose centers lie strictly within the radius of the sample
+        let (px, py) = (position.0 - 0.5, position.1 - 0.5);
+        let (x0, x1) = (
+            (px - self.radius).floor() as i64 + 1,
+            (px + self.radius).ceil() as i64 - 1,
+        );
+        let (y0, y1) = (
+            (py - self.radius).floor() as i64 + 1,
+            (py + self.radius).ceil() as i64 - 1,
+        );
+        for y in y0.max(0)..=y1 {
+            for x in x0.max(0)..=x1 {
+                let Some(index) = self.index((x as u32, y as u32)) else {
+                    continue;
+                };
+                let weight = self
+                    .filter
+                    .evaluate(x as f64 - px, y as f64 - py, self.radius);
+                if weight == 0.0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[index];
+                pixel.sum += weight * *color;
+                pixel.weight_sum += weight;
+                for (acc, aov) in self.aovs[index * self.aov_count..(index + 1) * self.aov_count]
+                    .iter_mut()
+                    .zip(aovs)
+                {
+                    *acc += weight * *aov;
+                }
+            }
+        }
+    }
+
+    pub fn merge(&mut self, tile: &Film) {
+        for (k, p) in tile.bounds.pixels().enumerate() {
+            let Some(index) = self.index(p) else {
+                continue;
+            };
+            let (acc, pixel) = (&mut self.pixels[index], &tile.pixels[k]);
+            acc.sum += pixel.sum;
+            acc.weight_sum += pixel.weight_sum;
+            acc.luminance_sum += pixel.luminance_sum;
+            acc.luminance_sq_sum += pixel.luminance_sq_sum;
+            for a in 0..self.aov_count {
+                self.aovs[index * self.aov_count + a] += tile.aovs[k * self.aov_count + a];
+            }
+        }
+    }
+
+    // Filtered color of a pixel, black outside the film or where no sample reached
+    pub fn pixel(&self, p: (u32, u32)) -> Color {
+        match self.index(p) {
+            Some(index) if self.pixels[index].weight_sum != 0.0 => {
+                self.pixels[index].sum / self.pixels[index].weight_sum
+            }
+            _ => Color::default(),
+        }
+    }
+
+    // The `k`th AOV of a pixel, filtered like `pixel`
+    pub fn aov(&self, p: (u32, u32), k: usize) -> Color {
+        match self.index(p) {
+            Some(index) if self.pixels[index].weight_sum != 0.0 => {
+                self.aovs[index * self.aov_count + k] / self.pixels[index].weight_sum
+            }
+            _ => Color::default(),
+        }
+    }
+
+    // Mean relative standard error of the per-pixel luminance estimates after `samples`
+    // samples per pixel.
+    pub fn noise_level(&self, samples: u32) -> f64 {
+        if samples < 2 {
+            return f64::INFINITY;
+        }
+        let n = samples as f64;
+        let total: f64 = self
+            .pixels
+            .par_iter()
+            .map(|pixel| {
+                let mean = pixel.luminance_sum / n;
+                let variance =
+                    ((pixel.luminance_sq_sum / n - mean * mean) * n / (n - 1.0)).max(0.0);
+                (variance / n).sqrt() / (mean + 1e-2)
+            })
+            .sum();
+        total / self.pixels.len() as f64
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     material::Material,
+    onb::Onb,
     ray::Ray,
     vec3::{dot, Point3, Vec3},
 };
@@ -10,8 +11,15 @@ pub struct HitRecord {
     pub p: Point3,
     pub t: f64,
     pub normal: Vec3,
+    // Normal materials shade with, on the same side as `normal` but free to bend away from it
+    pub shading_normal: Vec3,
+    // Unit direction of increasing `u`, zero where the surface has no parametrization
+    pub tangent: Vec3,
     pub mat: Option<Arc<dyn Material>>,
     pub front_face: bool,
+    pub u: f64,
+    pub v: f64,
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -22,9 +30,21 @@ impl HitRecord {
         } else {
             -*outward_normal
         };
+        self.shading_normal = self.normal;
+    }
+
+    // Local frame around the shading normal with `u` along the tangent where there is one, so
+    // anisotropic materials line up with the surface parametrization
+    pub fn shading_frame(&self) -> Onb {
+        Onb::from_tangent(&self.shading_normal, &self.tangent)
     }
 }
 
 pub trait Hittable: Sync {
     fn hit(&self, r: &Ray, interval: RangeInclusive<f64>, rec: &mut HitRecord) -> bool;
+
+    // Materials hits on the object can report, in a fixed order, for numbering them
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        Vec::new()
+    }
 }
@@ -1,4 +1,7 @@
-use crate::hittable::{HitRecord, Hittable};
+use crate::{
+    hittable::{HitRecord, Hittable},
+    material::Material,
+};
 use std::sync::Arc;
 
 #[derive(Default)]
@@ -27,14 +30,22 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = *interval.end();
 
-        for object in self.objects.iter() {
+        for (index, object) in self.objects.iter().enumerate() {
             if object.hit(r, *interval.start()..=closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
+                temp_rec.object_id = index as u32;
             }
         }
         *rec = temp_rec;
 
         hit_anything
     }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.materials())
+            .collect()
+    }
 }
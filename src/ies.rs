@@ -0,0 +1,156 @@
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+use std::io::{Error, ErrorKind, Read};
+
+// Type C photometric data from an IESNA LM-63 file. Vertical angles run from 0 at the nadir
+// to 180 straight up, horizontal angles counter-clockwise around the nadir seen from above.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // One row of candela values over the vertical angles per horizontal angle
+    candela: Vec<f64>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    pub fn parse(reader: &mut dyn Read) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let text = String::from_utf8_lossy(&data);
+
+        // Keywords come first and are skipped, the photometric data follows the TILT line
+        let start = text
+            .find("TILT=")
+            .ok_or_else(|| invalid("Missing TILT line in IES file"))?;
+        let (tilt, rest) = text[start + 5..]
+            .split_once('\n')
+            .unwrap_or((&text[start + 5..], ""));
+        let mut numbers = rest
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| invalid("Invalid number in IES file"))
+            });
+        let mut next = || numbers.next().unwrap_or(Err(invalid("Truncated IES file")));
+
+        // Tilt tables only matter for lamps mounted at an angle, so they are skipped
+        if tilt.trim() == "INCLUDE" {
+            let _geometry = next()?;
+            let count = next()? as usize;
+            for _ in 0..2 * count {
+                next()?;
+            }
+        }
+
+        let (_lamps, _lumens_per_lamp, multiplier) = (next()?, next()?, next()?);
+        let (vertical_count, horizontal_count) = (next()? as usize, next()? as usize);
+        let photometric_type = next()?;
+        let (_units, _width, _length, _height) = (next()?, next()?, next()?, next()?);
+        let (ballast_factor, ballast_lamp_factor, _watts) = (next()?, next()?, next()?);
+        if photometric_type != 1.0 {
+            return Err(invalid("Only type C IES photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid("IES file has no candela values"));
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let scale = multiplier * ballast_factor * ballast_lamp_factor;
+        let candela = (0..vertical_count * horizontal_count)
+            .map(|_| next().map(|c| scale * c))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let max_candela = candela.iter().copied().fold(0.0, f64::max);
+        Ok(Self {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            max_candela,
+        })
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    // Candela bilinearly interpolated between the measured angles, in degrees. Horizontal
+    // angles are folded according to the symmetry the file was measured with.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (
+            self.vertical_angles[0],
+            self.vertical_angles[self.vertical_angles.len() - 1],
+        );
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+
+        // Files measured with bilateral or quadrant symmetry only cover half or a quarter
+        // of the circle
+        let horizontal_last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let mut horizontal = horizontal.rem_euclid(360.0);
+        if horizontal_last <= 180.0 && horizontal > 180.0 {
+            horizontal = 360.0 - horizontal;
+        }
+        if horizontal_last <= 90.0 && horizontal > 90.0 {
+            horizontal = 180.0 - horizontal;
+        }
+
+        let (v0, v1, tv) = lerp_index(&self.vertical_angles, vertical);
+        let n = self.vertical_angles.len();
+        let row = |h: usize| (1.0 - tv) * self.candela[h * n + v0] + tv * self.candela[h * n + v1];
+
+        // Full-circle files may stop short of 360, past the last angle they wrap back to the
+        // first one
+        if horizontal_last > 180.0 && horizontal > horizontal_last {
+            let last = self.horizontal_angles.len() - 1;
+            let first = self.horizontal_angles[0] + 360.0;
+            let th = (horizontal - horizontal_last) / (first - horizontal_last);
+            return (1.0 - th) * row(last) + th * row(0);
+        }
+
+        let (h0, h1, th) = lerp_index(&self.horizontal_angles, horizontal);
+        (1.0 - th) * row(h0) + th * row(h1)
+    }
+
+    // Intensity towards `direction` relative to the peak, for a fixture aimed along `nadir`.
+    // The 0 degree horizontal plane contains +x, or -z when aimed along x.
+    pub fn evaluate(&self, direction: &Vec3, nadir: &Vec3) -> f64 {
+        if self.max_candela == 0.0 {
+            return 0.0;
+        }
+        let (d, n) = (unit_vector(direction), unit_vector(nadir));
+        let reference = if n.x().abs() > 0.99 {
+            Vec3(0.0, 0.0, -1.0)
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let u = unit_vector(&(reference - dot(&reference, &n) * n));
+        let v = cross(&u, &n);
+
+        let vertical = dot(&d, &n).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = dot(&d, &v).atan2(dot(&d, &u)).to_degrees();
+        self.candela(vertical, horizontal) / self.max_candela
+    }
+}
+
+// Indices of the angles around `x` and the blend between them, clamped at the ends
+fn lerp_index(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    let i = angles.partition_point(|&a| a <= x);
+    if i == 0 {
+        return (0, 0, 0.0);
+    }
+    if i == angles.len() {
+        return (i - 1, i - 1, 0.0);
+    }
+    let (a0, a1) = (angles[i - 1], angles[i]);
+    (i - 1, i, (x - a0) / (a1 - a0))
+}
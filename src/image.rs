@@ -1,3 +1,12 @@
+use crate::{
+    color::{self, Color},
+    tile::Tile,
+};
+use std::{
+    io::{Error, ErrorKind, Read, Write},
+    path::Path,
+};
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub struct ImageInfo {
     pub image_width: u32,
@@ -33,3 +42,374 @@ impl Default for ImageInfo {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropWindow {
+    Pixels { x0: u32, y0: u32, x1: u32, y1: u32 },
+    Normalized { x0: f64, y0: f64, x1: f64, y1: f64 },
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CropOutput {
+    Cropped,
+    #[default]
+    FullFrame,
+}
+
+impl CropWindow {
+    pub fn bounds(&self, image_info: &ImageInfo) -> Tile {
+        let (width, height) = (image_info.image_width, image_info.image_height);
+        let bounds = match *self {
+            CropWindow::Pixels { x0, y0, x1, y1 } => Tile {
+                x0: x0.min(width),
+                y0: y0.min(height),
+                x1: x1.min(width),
+                y1: y1.min(height),
+            },
+            CropWindow::Normalized { x0, y0, x1, y1 } => {
+                let (width, height) = (width as f64, height as f64);
+                Tile {
+                    x0: (x0.clamp(0.0, 1.0) * width).floor() as u32,
+                    y0: (y0.clamp(0.0, 1.0) * height).floor() as u32,
+                    x1: (x1.clamp(0.0, 1.0) * width).ceil() as u32,
+                    y1: (y1.clamp(0.0, 1.0) * height).ceil() as u32,
+                }
+            }
+        };
+        assert!(
+            bounds.x0 < bounds.x1 && bounds.y0 < bounds.y1,
+            "Crop window must cover at least one pixel"
+        );
+        bounds
+    }
+}
+
+// The first `count` whitespace-separated header tokens, skipping comments, followed by the
+// offset of the binary raster after the single whitespace that ends the header
+fn header_tokens(data: &[u8], count: usize) -> Option<(Vec<String>, usize)> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while tokens.len() < count {
+        match data.get(pos)? {
+            b'#' => {
+                while data.get(pos).is_some_and(|&c| c != b'\n') {
+                    pos += 1;
+                }
+            }
+            c if c.is_ascii_whitespace() => pos += 1,
+            _ => {
+                let start = pos;
+                while data.get(pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                    pos += 1;
+                }
+                tokens.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+            }
+        }
+    }
+    Some((tokens, pos + 1))
+}
+
+// Reads a PGM or PPM image (P2, P3, P5 or P6) with values scaled to [0, 1]
+pub fn read_pnm(reader: &mut dyn Read) -> Result<(ImageInfo, Vec<Color>), Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let (tokens, pos) = header_tokens(&data, 4).ok_or_else(|| invalid("Truncated PNM header"))?;
+
+    let parse = |token: &str| {
+        token
+            .parse::<u32>()
+            .map_err(|_| invalid("Invalid PNM header value"))
+    };
+    let (width, height, max_value) = (parse(&tokens[1])?, parse(&tokens[2])?, parse(&tokens[3])?);
+    let channels = match tokens[0].as_str() {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => return Err(invalid("Unsupported PNM format")),
+    };
+    let count = (width * height * channels) as usize;
+
+    let values: Vec<u32> = match tokens[0].as_str() {
+        "P2" | "P3" => String::from_utf8_lossy(&data[pos.min(data.len())..])
+            .split_whitespace()
+            .take(count)
+            .map(parse)
+            .collect::<Result<_, _>>()?,
+        _ if max_value < 256 => data
+            .get(pos..pos + count)
+            .map_or(vec![], |raster| raster.iter().map(|&b| b as u32).collect()),
+        _ => data.get(pos..pos + 2 * count).map_or(vec![], |raster| {
+            raster
+                .chunks(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+                .collect()
+        }),
+    };
+    if values.len() != count {
+        return Err(invalid("Truncated PNM raster"));
+    }
+
+    let scale = 1.0 / max_value as f64;
+    let pixels = values
+        .chunks(channels as usize)
+        .map(|c| match c {
+            [g] => scale * Color(*g as f64, *g as f64, *g as f64),
+            _ => scale * Color(c[0] as f64, c[1] as f64, c[2] as f64),
+        })
+        .collect();
+    Ok((ImageInfo::from_dim(width, height), pixels))
+}
+
+// Reads a color (PF) or greyscale (Pf) PFM, returning rows top to bottom
+pub fn read_pfm(reader: &mut dyn Read) -> Result<(ImageInfo, Vec<Color>), Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let (tokens, pos) = header_tokens(&data, 4).ok_or_else(|| invalid("Truncated PFM header"))?;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("Unsupported PFM format")),
+    };
+    let (width, height) = match (tokens[1].parse::<u32>(), tokens[2].parse::<u32>()) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(invalid("Invalid PFM size")),
+    };
+    // A negative scale marks little-endian data
+    let scale = tokens[3]
+        .parse::<f64>()
+        .map_err(|_| invalid("Invalid PFM scale"))?;
+
+    let count = (width * height * channels) as usize;
+    let raster = data
+        .get(pos..pos + 4 * count)
+        .ok_or_else(|| invalid("Truncated PFM raster"))?;
+    let values: Vec<f64> = raster
+        .chunks(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            let value = if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            value as f64
+        })
+        .collect();
+
+    let pixels = values
+        .chunks((width * channels) as usize)
+        .rev()
+        .flat_map(|row| row.chunks(channels as usize))
+        .map(|c| match c {
+            [g] => Color(*g, *g, *g),
+            _ => Color(c[0], c[1], c[2]),
+        })
+        .collect();
+    Ok((ImageInfo::from_dim(width, height), pixels))
+}
+
+// Reads a Radiance RGBE (.hdr) image in the standard -Y +X orientation, with either flat or
+// run-length encoded scanlines
+pub fn read_hdr(reader: &mut dyn Read) -> Result<(ImageInfo, Vec<Color>), Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if !data.starts_with(b"#?") {
+        return Err(invalid("Missing Radiance HDR signature"));
+    }
+
+    // Header lines up to the blank line, then the resolution line
+    let mut lines = data.split(|&c| c == b'\n');
+    let mut pos = 0;
+    for line in lines.by_ref() {
+        pos += line.len() + 1;
+        if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("Unsupported Radiance HDR format"));
+        }
+        if line.is_empty() {
+            break;
+        }
+    }
+    let resolution = lines
+        .next()
+        .ok_or_else(|| invalid("Truncated HDR header"))?;
+    pos += resolution.len() + 1;
+    let resolution = String::from_utf8_lossy(resolution);
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => match (width.parse::<u32>(), height.parse::<u32>()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(invalid("Invalid HDR resolution")),
+        },
+        _ => return Err(invalid("Unsupported HDR orientation")),
+    };
+
+    let truncated = || invalid("Truncated HDR raster");
+    let byte = |pos: &mut usize| {
+        let b = data.get(*pos).copied();
+        *pos += 1;
+        b.ok_or_else(truncated)
+    };
+    let mut rgbe = vec![[0u8; 4]; (width * height) as usize];
+    for row in rgbe.chunks_mut(width as usize) {
+        let rle = (8..32768).contains(&width)
+            && data.get(pos..pos + 4).is_some_and(|h| {
+                h[0] == 2 && h[1] == 2 && ((h[2] as u32) << 8 | h[3] as u32) == width
+            });
+        if !rle {
+            for pixel in row.iter_mut() {
+                for c in pixel.iter_mut() {
+                    *c = byte(&mut pos)?;
+                }
+            }
+            continue;
+        }
+
+        // Each component is stored separately as runs and literal spans
+        pos += 4;
+        for component in 0..4 {
+            let mut x = 0;
+            while x < row.len() {
+                let count = byte(&mut pos)? as usize;
+                if count > 128 {
+                    let value = byte(&mut pos)?;
+                    for pixel in row.iter_mut().skip(x).take(count - 128) {
+                        pixel[component] = value;
+                    }
+                    x += count - 128;
+                } else if count > 0 {
+                    for pixel in row.iter_mut().skip(x).take(count) {
+                        pixel[component] = byte(&mut pos)?;
+                    }
+                    x += count;
+                } else {
+                    return Err(invalid("Invalid HDR run length"));
+                }
+            }
+        }
+    }
+
+    let pixels = rgbe
+        .iter()
+        .map(|&[r, g, b, e]| {
+            if e == 0 {
+                return Color::default();
+            }
+            let scale = 2f64.powi(e as i32 - 136);
+            scale * Color(r as f64, g as f64, b as f64)
+        })
+        .collect();
+    Ok((ImageInfo::from_dim(width, height), pixels))
+}
+
+// Reads a PNG of any color type with values scaled to [0, 1]. Like `read_pnm` this leaves
+// sRGB encoded values as they are, which is what data such as normal maps want.
+pub fn read_png(reader: &mut dyn Read) -> Result<(ImageInfo, Vec<Color>), Error> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(Error::other)?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(Error::other)?;
+
+    let values: Vec<f64> = match info.bit_depth {
+        png::BitDepth::Sixteen => data[..info.buffer_size()]
+            .chunks(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / 65535.0)
+            .collect(),
+        _ => data[..info.buffer_size()]
+            .iter()
+            .map(|&b| b as f64 / 255.0)
+            .collect(),
+    };
+    // Alpha is dropped
+    let pixels = values
+        .chunks(info.color_type.samples())
+        .map(|c| match c {
+            [g] | [g, _] => Color(*g, *g, *g),
+            _ => Color(c[0], c[1], c[2]),
+        })
+        .collect();
+    Ok((ImageInfo::from_dim(info.width, info.height), pixels))
+}
+
+pub fn write_png(
+    file: &mut dyn Write,
+    image_info: &ImageInfo,
+    pixels: &[Color],
+) -> Result<(), Error> {
+    let mut encoder = png::Encoder::new(file, image_info.image_width, image_info.image_height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = pixels.iter().flat_map(color::to_rgb8).collect();
+    let mut writer = encoder.write_header().map_err(Error::other)?;
+    writer.write_image_data(&data).map_err(Error::other)?;
+    writer.finish().map_err(Error::other)
+}
+
+// Little-endian PFM, rows stored bottom to top
+pub fn write_pfm(
+    file: &mut dyn Write,
+    image_info: &ImageInfo,
+    pixels: &[Color],
+) -> Result<(), Error> {
+    let (width, height) = (image_info.image_width, image_info.image_height);
+    file.write_all(format!("PF\n{} {}\n-1.0\n", width, height).as_bytes())?;
+
+    for row in pixels.chunks(width as usize).rev() {
+        let data: Vec<u8> = row
+            .iter()
+            .flat_map(|c| [c.x() as f32, c.y() as f32, c.z() as f32])
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        file.write_all(&data)?;
+    }
+    Ok(())
+}
+
+// Multi-layer OpenEXR with one RGB layer per named pass
+pub fn write_exr(
+    path: &Path,
+    image_info: &ImageInfo,
+    layers: &[(&str, &[Color])],
+) -> Result<(), Error> {
+    use exr::prelude::{
+        AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds,
+        Layer, LayerAttributes, SmallVec, Text, WritableImage,
+    };
+
+    let size = (
+        image_info.image_width as usize,
+        image_info.image_height as usize,
+    );
+    let layers: SmallVec<[_; 2]> = layers
+        .iter()
+        .map(|(name, pixels)| {
+            let channel = |label: &str, component: fn(&Color) -> f64| {
+                AnyChannel::new(
+                    label,
+                    FlatSamples::F32(pixels.iter().map(|c| component(c) as f32).collect()),
+                )
+            };
+            let channels = AnyChannels::sort(SmallVec::from_vec(vec![
+                channel("R", Color::x),
+                channel("G", Color::y),
+                channel("B", Color::z),
+            ]));
+            let attributes = LayerAttributes::named(Text::from(*name));
+            Layer::new(size, attributes, Encoding::SMALL_LOSSLESS, channels)
+        })
+        .collect();
+
+    Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+        layers,
+    )
+    .write()
+    .to_file(path)
+    .map_err(Error::other)
+}
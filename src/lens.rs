@@ -0,0 +1,313 @@
+use crate::{
+    ray::Ray,
+    vec3::{dot, unit_vector, Point3, Vec3},
+};
+use std::io::{Error, ErrorKind};
+
+// radius thickness ior aperture, in millimetres, front element first
+pub const DOUBLE_GAUSS_50MM: &str = "
+# D-GAUSS F/2 22deg HFOV, US patent 2,673,491 Tronnier, scaled to 50 mm
+29.475   3.76   1.67   25.2
+84.83    0.12   1      25.2
+19.275   4.025  1.67   23
+40.77    3.275  1.699  23
+12.75    5.705  1      18
+0        4.5    0      17.1
+-14.495  1.18   1.603  17
+40.77    6.065  1.658  20
+-20.385  0.19   1      20
+437.065  2.22   1.717  20
+-39.73   0      1      20
+";
+
+// Diagonal of a 36 x 24 mm full-frame sensor, in millimetres
+pub const FULL_FRAME_DIAGONAL: f64 = 43.27;
+
+// A spherical interface, `radius` 0 marks the aperture stop and `ior` 0 means air.
+// `thickness` is the distance to the next interface towards the film.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    pub radius: f64,
+    pub thickness: f64,
+    pub ior: f64,
+    pub aperture: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    pub film_diagonal: f64,
+    exposure: f64,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f64) -> Self {
+        assert!(
+            !elements.is_empty(),
+            "Lens system needs at least one element"
+        );
+        Self {
+            elements,
+            film_diagonal,
+            exposure: 1.0,
+        }
+    }
+
+    // Parses a prescription table with one `radius thickness ior aperture` row per element.
+    // `film_diagonal` is in millimetres like the table, and `scale` converts both into scene
+    // units, e.g. 0.001 for metres.
+    pub fn from_prescription(table: &str, scale: f64, film_diagonal: f64) -> Result<Self, Error> {
+        let elements = table
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let values = line
+                    .split_whitespace()
+                    .map(|value| value.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                match values[..] {
+                    [radius, thickness, ior, aperture] => Ok(LensElement {
+                        radius: radius * scale,
+                        thickness: thickness * scale,
+                        ior,
+                        aperture: aperture * scale,
+                    }),
+                    _ => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Expected 4 values per lens element, got `{}`", line),
+                    )),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if elements.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Empty lens prescription",
+            ));
+        }
+        Ok(Self::new(elements, film_diagonal * scale))
+    }
+
+    pub fn with_stop_aperture(mut self, aperture: f64) -> Self {
+        for element in self.elements.iter_mut().filter(|e| e.radius == 0.0) {
+            element.aperture = aperture;
+        }
+        self
+    }
+
+    pub fn film_size(&self, aspect_ratio: f64) -> (f64, f64) {
+        let height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        (aspect_ratio * height, height)
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.elements.last().unwrap().thickness
+    }
+
+    fn rear_aperture(&self) -> f64 {
+        self.elements.last().unwrap().aperture
+    }
+
+    // Moves the film so that objects `focus_dist` in front of it are sharp, using the
+    // thick lens approximation of the system. Distances closer than the lens can focus are
+    // clamped to the nearest one it can. The paraxial rays only depend on the lens, so a
+    // system that blocks them fails on the first focus, when the camera is built.
+    pub fn focused(&self, focus_dist: f64) -> Self {
+        let x = 0.001 * self.film_diagonal;
+
+        let scene_ray = Ray::new(&Point3(x, 0.0, -self.front_z() - 1.0), &Vec3(0.0, 0.0, 1.0));
+        let film_ray = self
+            .trace_from_scene(&scene_ray)
+            .expect("Paraxial ray from the scene blocked by lens system");
+        let (pz0, fz0) = cardinal_points(&scene_ray, &film_ray);
+
+        let film_ray = Ray::new(&Point3(x, 0.0, 1.0 - self.rear_z()), &Vec3(0.0, 0.0, -1.0));
+        let scene_ray = self
+            .trace_from_film(&film_ray)
+            .expect("Paraxial ray from the film blocked by lens system");
+        let (pz1, _) = cardinal_points(&film_ray, &scene_ray);
+
+        let f = fz0 - pz0;
+        let nearest = pz0 - pz1 + 4.0 * f;
+        if focus_dist < nearest {
+            log::warn!(
+                "Focus distance {} is closer than the lens can focus, using {}",
+                focus_dist,
+                nearest
+            );
+        }
+        let z = -focus_dist.max(nearest);
+        let c = ((pz1 - z - pz0) * (pz1 - z - 4.0 * f - pz0)).max(0.0);
+        let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+
+        let mut lens = self.clone();
+        lens.elements.last_mut().unwrap().thickness += delta;
+        lens.exposure = match lens.center_transmittance() {
+            0.0 => {
+                log::warn!("Lens system blocks all light from the film center");
+                1.0
+            }
+            transmittance => transmittance.recip(),
+        };
+        lens
+    }
+
+    // Fraction of rays leaving the film center that make it through the lens, used to
+    // normalise exposure so that the image center is not darkened by the sampling.
+    fn center_transmittance(&self) -> f64 {
+        let n = 64;
+        let passed = (0..n * n)
+            .filter(|k| {
+                let (a, b) = (
+                    ((k % n) as f64 + 0.5) / n as f64,
+                    ((k / n) as f64 + 0.5) / n as f64,
+                );
+                self.generate_ray(&Point3(0.0, 0.0, 0.0), a, b).is_some()
+            })
+            .count();
+        passed as f64 / (n * n) as f64
+    }
+
+    // Traces a ray from `film` (lens space, film at z = 0 and the scene towards -z) through
+    // a point on the rear element picked by `(a, b)` in [0, 1). Returns the outgoing ray in
+    // lens space together with its radiometric weight, or `None` if it was vignetted.
+    pub fn generate_ray(&self, film: &Point3, a: f64, b: f64) -> Option<(Ray, f64)> {
+        let (r, phi) = (
+            0.5 * self.rear_aperture() * a.sqrt(),
+            2.0 * std::f64::consts::PI * b,
+        );
+        let rear = Point3(r * phi.cos(), r * phi.sin(), -self.rear_z());
+
+        let film_ray = Ray::new(film, &(rear - film));
+        let scene_ray = self.trace_from_film(&film_ray)?;
+
+        let cos_theta = -unit_vector(film_ray.direction()).z();
+        Some((scene_ray, cos_theta.powi(4) * self.exposure))
+    }
+
+    fn trace_from_film(&self, r: &Ray) -> Option<Ray> {
+        let (mut origin, mut direction) = (*r.origin(), *r.direction());
+        let mut element_z = 0.0;
+
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            element_z -= element.thickness;
+            let (t, normal) = intersect_element(element, element_z, &origin, &direction)?;
+
+            origin += t * direction;
+            if origin.x().powi(2) + origin.y().powi(2) > (element.aperture / 2.0).powi(2) {
+                return None;
+            }
+
+            if element.radius != 0.0 {
+                let eta_i = medium_ior(element.ior);
+                let eta_t = if i > 0 {
+                    medium_ior(self.elements[i - 1].ior)
+                } else {
+                    1.0
+                };
+                direction = refract(&-unit_vector(&direction), &normal, eta_i / eta_t)?;
+            }
+        }
+
+        Some(Ray::new(&origin, &direction))
+    }
+
+    fn trace_from_scene(&self, r: &Ray) -> Option<Ray> {
+        let (mut origin, mut direction) = (*r.origin(), *r.direction());
+        let mut element_z = -self.front_z();
+
+        for (i, element) in self.elements.iter().enumerate() {
+            let (t, normal) = intersect_element(element, element_z, &origin, &direction)?;
+
+            origin += t * direction;
+            if origin.x().powi(2) + origin.y().powi(2) > (element.aperture / 2.0).powi(2) {
+                return None;
+            }
+
+            if element.radius != 0.0 {
+                let eta_i = if i > 0 {
+                    medium_ior(self.elements[i - 1].ior)
+                } else {
+                    1.0
+                };
+                let eta_t = medium_ior(element.ior);
+                direction = refract(&-unit_vector(&direction), &normal, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+
+        Some(Ray::new(&origin, &direction))
+    }
+}
+
+fn medium_ior(ior: f64) -> f64 {
+    if ior == 0.0 {
+        1.0
+    } else {
+        ior
+    }
+}
+
+// Intersection distance and the normal facing back along the ray.
+fn intersect_element(
+    element: &LensElement,
+    element_z: f64,
+    origin: &Point3,
+    direction: &Vec3,
+) -> Option<(f64, Vec3)> {
+    if element.radius == 0.0 {
+        let t = (element_z - origin.z()) / direction.z();
+        return (t >= 0.0).then_some((t, Vec3(0.0, 0.0, -direction.z().signum())));
+    }
+
+    let oc = origin - Point3(0.0, 0.0, element_z + element.radius);
+    let a = direction.length_squared();
+    let h = dot(direction, &oc);
+    let c = oc.length_squared() - element.radius * element.radius;
+    let discriminant = h * h - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-h - sqrtd) / a, (-h + sqrtd) / a);
+    let use_closer = (direction.z() > 0.0) ^ (element.radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = unit_vector(&(oc + t * direction));
+    let normal = if dot(&normal, direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some((t, normal))
+}
+
+// `wi` points away from the surface on the same side as `n`, `eta` is eta_i / eta_t.
+fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = dot(n, wi);
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * n)
+}
+
+// Principal plane and focal point along z for a paraxial ray pair.
+fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (f64, f64) {
+    let (o, d) = (r_out.origin(), r_out.direction());
+    let tf = -o.x() / d.x();
+    let tp = (r_in.origin().x() - o.x()) / d.x();
+    (o.z() + tp * d.z(), o.z() + tf * d.z())
+}
@@ -1,14 +1,36 @@
 use std::f64::consts::PI;
 
+pub mod animation;
+pub mod aov;
+pub mod aperture;
+pub mod bump;
 pub mod camera;
 pub mod color;
+pub mod denoise;
+pub mod environment;
+pub mod film;
 pub mod hittable;
 pub mod hittable_list;
+pub mod ies;
 pub mod image;
+pub mod lens;
+pub mod light;
+pub mod light_list;
 pub mod material;
+pub mod microfacet;
+pub mod onb;
+pub mod principled;
 pub mod ray;
+pub mod sampling;
+pub mod scene;
 pub mod scenes;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod stereo;
+pub mod texture;
+pub mod tile;
+pub mod transform;
 pub mod vec3;
 
 fn degrees_to_radians(degrees: f64) -> f64 {
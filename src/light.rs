@@ -0,0 +1,156 @@
+use crate::{
+    color::Color,
+    ies::IesProfile,
+    onb::Onb,
+    vec3::{dot, random_int_unit_disk, unit_vector, Point3, Vec3},
+};
+use std::{fmt::Debug, sync::Arc};
+
+pub struct LightSample {
+    // Unit vector from the shaded point towards the light
+    pub direction: Vec3,
+    // Distance to the sampled point on the light, infinite for directional lights
+    pub distance: f64,
+    // Incident radiance divided by the density of the sample
+    pub radiance: Color,
+}
+
+// Lights that rays cannot hit, reached only by sampling them from the shaded point
+pub trait Light: Debug + Send + Sync {
+    fn sample(&self, p: &Point3) -> Option<LightSample>;
+}
+
+// `intensity` is the radiant intensity in every direction. A nonzero `radius` spreads the
+// light over a sphere for soft shadows, keeping the same total power. An IES `profile`
+// shapes the emission of a fixture pointing down, with `intensity` at its peak.
+#[derive(Default, Debug, Clone)]
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+    pub radius: f64,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let emission = self.profile.as_ref().map_or(1.0, |profile| {
+            profile.evaluate(&(p - self.position), &Vec3(0.0, -1.0, 0.0))
+        });
+        if emission == 0.0 {
+            return None;
+        }
+
+        let to_light = sample_sphere(&self.position, self.radius, p) - p;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: (emission / (distance * distance)) * self.intensity,
+        })
+    }
+}
+
+// Shines along `direction`, at full intensity within `inner_angle` of it and fading to none
+// at `outer_angle`. Angles are in degrees from the axis. An IES `profile` is aimed along
+// `direction` and shapes the emission within the cone.
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+    pub position: Point3,
+    pub direction: Vec3,
+    pub intensity: Color,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub radius: f64,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            position: Point3::default(),
+            direction: Vec3(0.0, -1.0, 0.0),
+            intensity: Color::default(),
+            inner_angle: 30.0,
+            outer_angle: 45.0,
+            radius: 0.0,
+            profile: None,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let cos_angle = dot(
+            &unit_vector(&(p - self.position)),
+            &unit_vector(&self.direction),
+        );
+        let (cos_inner, cos_outer) = (
+            self.inner_angle.to_radians().cos(),
+            self.outer_angle.to_radians().cos(),
+        );
+        let falloff = if cos_inner > cos_outer {
+            let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        } else if cos_angle >= cos_outer {
+            1.0
+        } else {
+            0.0
+        };
+        let emission = falloff
+            * self.profile.as_ref().map_or(1.0, |profile| {
+                profile.evaluate(&(p - self.position), &self.direction)
+            });
+        if emission == 0.0 {
+            return None;
+        }
+
+        let to_light = sample_sphere(&self.position, self.radius, p) - p;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: (emission / (distance * distance)) * self.intensity,
+        })
+    }
+}
+
+// Parallel light travelling along `direction`, with `irradiance` on surfaces facing it
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub irradiance: Color,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3(0.0, -1.0, 0.0),
+            irradiance: Color::default(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -unit_vector(&self.direction),
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+}
+
+// Point on the disk of a sphere's silhouette as seen from `p`
+fn sample_sphere(center: &Point3, radius: f64, p: &Point3) -> Point3 {
+    if radius <= 0.0 {
+        return *center;
+    }
+    let disk = random_int_unit_disk();
+    center + radius * Onb::new(&(p - center)).transform(&disk)
+}
@@ -0,0 +1,21 @@
+use crate::light::Light;
+use std::sync::Arc;
+
+#[derive(Default, Debug)]
+pub struct LightList {
+    lights: Vec<Arc<dyn Light>>,
+}
+
+impl LightList {
+    pub fn clear(&mut self) {
+        self.lights.clear()
+    }
+
+    pub fn add(&mut self, light: Arc<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    pub fn lights(&self) -> &[Arc<dyn Light>] {
+        &self.lights
+    }
+}
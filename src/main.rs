@@ -1,4 +1,6 @@
-use rtrs::{camera::CameraBuilder, image::ImageInfo, scenes::test_scene, vec3::Point3};
+use rtrs::{
+    camera::CameraBuilder, image::ImageInfo, scene::Scene, scenes::test_scene, vec3::Point3,
+};
 use std::{fs::File, io::BufWriter};
 
 fn main() {
@@ -8,7 +10,7 @@ fn main() {
     let file = File::create("img.ppm").unwrap();
     let mut file = BufWriter::new(file);
 
-    let world = test_scene();
+    let scene = Scene::from(test_scene());
 
     let cam = CameraBuilder::new(
         &ImageInfo::from_aspect(144, 16.0 / 9.0),
@@ -22,5 +24,5 @@ fn main() {
     .focus_dist(10.0)
     .build();
 
-    cam.render(&mut file, &world).unwrap();
+    cam.render(&mut file, &scene).unwrap();
 }
@@ -1,18 +1,59 @@
 use crate::{
-    color::Color,
+    color::{luminance, Color},
     hittable::HitRecord,
+    microfacet::{self, Ggx},
     ray::Ray,
+    spectrum::{self, Dispersion, Wavelengths},
     vec3::{dot, random_unit_vector, reflect, refract, unit_vector, Vec3},
 };
+use std::{
+    f64::consts::PI,
+    sync::{Arc, OnceLock},
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lobe {
+    #[default]
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub lobe: Lobe,
+    // Density of `scattered`, left at zero by lobes that light sampling cannot hit
+    pub pdf: f64,
+}
 
 pub trait Material: Send + Sync {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool;
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // Bends `rec.shading_normal` before anything else looks at the hit, for surface detail
+    // that is not in the geometry
+    fn perturb(&self, _r_in: &Ray, _rec: &mut HitRecord) {}
+
+    // Whether the colors returned for `r_in` are already sampled at its wavelengths instead of
+    // being RGB, for materials whose reflectance only makes sense spectrally
+    fn spectral(&self, _r_in: &Ray) -> bool {
+        false
+    }
+
+    // BSDF times the cosine term for light arriving from `direction`, for light sampling
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::default()
+    }
+
+    // Density with which `scatter` picks `direction`
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -21,21 +62,25 @@ pub struct Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + random_unit_vector();
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut scatter_direction = rec.shading_normal + random_unit_vector();
         if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+            scatter_direction = rec.shading_normal;
         }
-        *scattered = Ray::new(&rec.p, &scatter_direction);
-        *attenuation = self.albedo;
+        srec.scattered = Ray::with_time(&rec.p, &scatter_direction, r_in.time());
+        srec.attenuation = self.albedo;
+        srec.lobe = Lobe::Diffuse;
+        srec.pdf = self.pdf(r_in, rec, &scatter_direction);
         true
     }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.pdf(r_in, rec, direction) * self.albedo
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        dot(&unit_vector(direction), &rec.shading_normal).max(0.0) / PI
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -54,30 +99,274 @@ impl Default for Metal {
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let fuzz_vector = match self.fuzz {
             Some(fuzz) => fuzz.clamp(0.0, 1.0) * random_unit_vector(),
             None => Vec3::default(),
         };
-        let reflected = unit_vector(&reflect(r_in.direction(), &rec.normal)) + fuzz_vector;
-        *scattered = Ray::new(&rec.p, &reflected);
-        *attenuation = self.albedo;
-        dot(scattered.direction(), &rec.normal) > 0.0
+        let reflected = unit_vector(&reflect(r_in.direction(), &rec.shading_normal)) + fuzz_vector;
+        srec.scattered = Ray::with_time(&rec.p, &reflected, r_in.time());
+        srec.attenuation = self.albedo;
+        srec.lobe = Lobe::Specular;
+        dot(srec.scattered.direction(), &rec.shading_normal) > 0.0
+    }
+}
+
+// Transparent film a fraction of a wavelength thick, as on soap bubbles, oil slicks and
+// anodized metal. Light reflected off its top and bottom interferes, so the reflectance
+// varies over the spectrum with `thickness`, in nanometers, and the viewing angle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    // Reflectance of the film lying over a base of index `eta + ik`, given per RGB channel.
+    // Paths traced at a set of wavelengths get the film at exactly those: spectral samples
+    // for a hero and its companions, gray for a single wavelength. Everything else gets the
+    // film integrated against the color matching functions.
+    pub fn reflectance(
+        &self,
+        cos_theta: f64,
+        eta: &Color,
+        k: &Color,
+        wavelengths: Option<Wavelengths>,
+    ) -> Color {
+        let at = |wavelength: f64| {
+            microfacet::fresnel_thin_film(
+                cos_theta,
+                self.ior,
+                self.thickness,
+                channel_at(eta, wavelength),
+                channel_at(k, wavelength),
+                wavelength,
+            )
+        };
+        match wavelengths {
+            Some(Wavelengths::Single(wavelength)) => {
+                let r = at(wavelength);
+                Color(r, r, r)
+            }
+            Some(wavelengths) => {
+                let [a, b, c] = wavelengths.all();
+                Color(at(a), at(b), at(c))
+            }
+            None => film_rgb(
+                &(0..FILM_STEPS)
+                    .map(film_wavelength)
+                    .map(at)
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+}
+
+// Films are integrated to RGB over this many wavelengths
+const FILM_STEPS: usize = 32;
+
+fn film_wavelength(i: usize) -> f64 {
+    let t = (i as f64 + 0.5) / FILM_STEPS as f64;
+    spectrum::WAVELENGTH_MIN + t * (spectrum::WAVELENGTH_MAX - spectrum::WAVELENGTH_MIN)
+}
+
+// RGB of a quantity sampled at the `film_wavelength`s, against the color matching functions
+fn film_rgb(samples: &[f64]) -> Color {
+    let (mut sum, mut weight_sum) = (Color::default(), Color::default());
+    for (i, sample) in samples.iter().enumerate() {
+        let weight = spectrum::wavelength_weight(film_wavelength(i));
+        sum += *sample * weight;
+        weight_sum += weight;
     }
+    Color(
+        sum.x() / weight_sum.x(),
+        sum.y() / weight_sum.y(),
+        sum.z() / weight_sum.z(),
+    )
 }
 
+// Value of a per channel quantity at `wavelength`, interpolated between the channels at their
+// dominant wavelengths
+fn channel_at(c: &Color, wavelength: f64) -> f64 {
+    if wavelength < 550.0 {
+        let t = ((wavelength - 450.0) / 100.0).max(0.0);
+        (1.0 - t) * c.z() + t * c.y()
+    } else {
+        let t = ((wavelength - 550.0) / 100.0).min(1.0);
+        (1.0 - t) * c.y() + t * c.x()
+    }
+}
+
+// Whether a film evaluated along `r_in` gives spectral samples rather than RGB
+fn hero_wavelengths(r_in: &Ray) -> bool {
+    matches!(r_in.wavelengths(), Some(Wavelengths::Hero(_)))
+}
+
+// GGX microfacet conductor with complex index of refraction `eta + ik`, given per RGB
+// channel. Anisotropic highlights stretch along the surface tangent where there is one.
+// An oxide `film` on top makes it iridescent.
+#[derive(Debug, Clone, Copy)]
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+    pub anisotropy: f64,
+    pub film: Option<ThinFilm>,
+}
+
+impl Default for Conductor {
+    fn default() -> Self {
+        Conductor::aluminium(0.3)
+    }
+}
+
+impl Conductor {
+    fn preset(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            roughness,
+            anisotropy: 0.0,
+            film: None,
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::preset(
+            Color(0.143, 0.374, 1.442),
+            Color(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::preset(
+            Color(0.155, 0.117, 0.138),
+            Color(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::preset(
+            Color(0.200, 0.924, 1.102),
+            Color(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::preset(
+            Color(1.657, 0.880, 0.521),
+            Color(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn iron(roughness: f64) -> Self {
+        Self::preset(
+            Color(2.870, 2.950, 2.650),
+            Color(3.080, 2.930, 2.810),
+            roughness,
+        )
+    }
+
+    pub fn platinum(roughness: f64) -> Self {
+        Self::preset(
+            Color(2.380, 2.070, 1.850),
+            Color(4.260, 3.710, 3.130),
+            roughness,
+        )
+    }
+
+    fn fresnel(&self, cos_theta: f64, r_in: &Ray) -> Color {
+        match &self.film {
+            Some(film) => film.reflectance(cos_theta, &self.eta, &self.k, r_in.wavelengths()),
+            None => microfacet::fresnel_conductor(cos_theta, &self.eta, &self.k),
+        }
+    }
+
+    // Incoming and outgoing directions in the shading frame of the hit
+    fn local(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Ggx, Vec3, Vec3) {
+        let onb = rec.shading_frame();
+        (
+            Ggx::new(self.roughness, self.anisotropy),
+            onb.to_local(&-unit_vector(r_in.direction())),
+            onb.to_local(&unit_vector(direction)),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let onb = rec.shading_frame();
+        let ggx = Ggx::new(self.roughness, self.anisotropy);
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let h = ggx.sample_visible(&wo, fastrand::f64(), fastrand::f64());
+        let wi = reflect(&-wo, &h);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        // The sampling density cancels D and one masking term, leaving F G2 / G1
+        let fresnel = self.fresnel(dot(&wo, &h), r_in);
+        srec.attenuation = (ggx.g(&wo, &wi) / ggx.g1(&wo)) * fresnel;
+        srec.scattered = Ray::with_time(&rec.p, &onb.transform(&wi), r_in.time());
+        srec.lobe = Lobe::Specular;
+        srec.pdf = ggx.pdf(&wo, &h) / (4.0 * dot(&wo, &h));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (ggx, wo, wi) = self.local(r_in, rec, direction);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+        let h = unit_vector(&(wo + wi));
+        let fresnel = self.fresnel(dot(&wo, &h), r_in);
+        (ggx.d(&h) * ggx.g(&wo, &wi) / (4.0 * wo.z())) * fresnel
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (ggx, wo, wi) = self.local(r_in, rec, direction);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(&(wo + wi));
+        ggx.pdf(&wo, &h) / (4.0 * dot(&wo, &h))
+    }
+
+    fn spectral(&self, r_in: &Ray) -> bool {
+        self.film.is_some() && hero_wavelengths(r_in)
+    }
+}
+
+// `albedo` tints light as it enters, `absorption` dims it per unit distance travelled inside.
+// With `dispersion` set the index follows the path's hero wavelength instead, which carries on
+// alone past the surface. Paths that have none yet pick one here.
 pub struct Dielectric {
     pub refractive_index: f64,
     pub albedo: Color,
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
+    // Absorbs just enough that `transmittance` of the light remains after `distance`. Rays
+    // do not carry the medium they are in, so absorption only applies along stretches that
+    // end on this object's own back face. Light passing through another object nested
+    // inside, such as a bubble or an ice cube in a drink, is not absorbed on either side of it.
+    pub fn transmittance(self, transmittance: Color, distance: f64) -> Self {
+        Self {
+            absorption: absorption(&transmittance, distance),
+            ..self
+        }
+    }
+
     fn reflectance(cosine: f64, refractive_index: f64) -> f64 {
         let r0 = ((1.0 - refractive_index) / (1.0 + refractive_index)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
@@ -89,43 +378,617 @@ impl Default for Dielectric {
         Self {
             refractive_index: 1.0,
             albedo: Vec3(1.0, 1.0, 1.0),
+            absorption: Color::default(),
+            dispersion: None,
         }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let (refractive_index, wavelengths) = match &self.dispersion {
+            Some(dispersion) => {
+                let wavelength = r_in
+                    .wavelength()
+                    .unwrap_or_else(spectrum::sample_wavelength);
+                (
+                    dispersion.refractive_index(wavelength),
+                    Some(Wavelengths::Single(wavelength)),
+                )
+            }
+            None => (self.refractive_index, r_in.wavelengths()),
+        };
         let ri = if rec.front_face {
-            1.0 / self.refractive_index
+            1.0 / refractive_index
         } else {
-            self.refractive_index
+            refractive_index
         };
 
         let unit_direction = unit_vector(r_in.direction());
-        let cos_theta = -dot(&unit_direction, &rec.normal);
+        let cos_theta = -dot(&unit_direction, &rec.shading_normal);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = ri * sin_theta > 1.0;
         let direction =
             if cannot_refract || Dielectric::reflectance(cos_theta, ri) > fastrand::f64() {
-                *attenuation = Color(1.0, 1.0, 1.0);
-                reflect(&unit_direction, &rec.normal)
+                srec.attenuation = Color(1.0, 1.0, 1.0);
+                srec.lobe = Lobe::Specular;
+                reflect(&unit_direction, &rec.shading_normal)
             } else {
-                *attenuation = if rec.front_face {
+                srec.lobe = Lobe::Transmission;
+                srec.attenuation = if rec.front_face {
                     self.albedo
                 } else {
                     Color(1.0, 1.0, 1.0)
                 };
-                refract(&unit_direction, &rec.normal, ri)
+                refract(&unit_direction, &rec.shading_normal, ri)
             };
 
-        *scattered = Ray::new(&rec.p, &direction);
+        srec.attenuation = srec.attenuation * medium_transmittance(&self.absorption, r_in, rec);
+        // From here on the path only carries the color of its wavelength
+        if let (None, Some(wavelengths)) = (r_in.wavelengths(), wavelengths) {
+            srec.attenuation = srec.attenuation * spectrum::wavelength_weight(wavelengths.hero());
+        }
+        srec.scattered =
+            Ray::with_time(&rec.p, &direction, r_in.time()).with_wavelengths(wavelengths);
         true
     }
 }
+
+// Dielectric with a GGX rough interface that both reflects and refracts (Walter et al. 2007),
+// for frosted glass and similar. Like `Dielectric`, `albedo` tints light entering the surface
+// and `absorption` dims it inside, while radiance is not rescaled by the squared index, which
+// cancels on leaving again.
+#[derive(Debug, Clone, Copy)]
+pub struct RoughDielectric {
+    pub refractive_index: f64,
+    pub albedo: Color,
+    pub absorption: Color,
+    pub roughness: f64,
+    pub anisotropy: f64,
+}
+
+impl Default for RoughDielectric {
+    fn default() -> Self {
+        Self {
+            refractive_index: 1.5,
+            albedo: Color(1.0, 1.0, 1.0),
+            absorption: Color::default(),
+            roughness: 0.3,
+            anisotropy: 0.0,
+        }
+    }
+}
+
+impl RoughDielectric {
+    // Same as `Dielectric::transmittance`, with the same limit on nested objects
+    pub fn transmittance(self, transmittance: Color, distance: f64) -> Self {
+        Self {
+            absorption: absorption(&transmittance, distance),
+            ..self
+        }
+    }
+
+    // Index of the side `direction` leaves into over that of the side the ray came from,
+    // along with the incoming and outgoing directions in the shading frame of the hit
+    fn local(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Ggx, f64, Vec3, Vec3) {
+        let onb = rec.shading_frame();
+        (
+            Ggx::new(self.roughness, self.anisotropy),
+            self.eta(rec),
+            onb.to_local(&-unit_vector(r_in.direction())),
+            onb.to_local(&unit_vector(direction)),
+        )
+    }
+
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.refractive_index
+        } else {
+            1.0 / self.refractive_index
+        }
+    }
+
+    fn tint(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.albedo
+        } else {
+            Color(1.0, 1.0, 1.0)
+        }
+    }
+
+    // Microfacet normal joining `wo` and `wi`, facing the incoming side, along with whether
+    // the pair is a reflection. None when no microfacet facing `wo` connects the two.
+    fn half_vector(eta: f64, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, bool)> {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+        let reflection = wi.z() > 0.0;
+        let h = if reflection {
+            *wo + *wi
+        } else {
+            *wo + eta * *wi
+        };
+        if h.near_zero() {
+            return None;
+        }
+        let h = unit_vector(&h);
+        let h = if h.z() < 0.0 { -h } else { h };
+        if dot(wo, &h) <= 0.0 || (dot(wi, &h) > 0.0) != reflection {
+            return None;
+        }
+        Some((h, reflection))
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let onb = rec.shading_frame();
+        let ggx = Ggx::new(self.roughness, self.anisotropy);
+        let eta = self.eta(rec);
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        // Reflect or refract through the sampled microfacet in proportion to its Fresnel
+        // reflectance, which then cancels along with D and one masking term
+        let h = ggx.sample_visible(&wo, fastrand::f64(), fastrand::f64());
+        let fresnel = microfacet::fresnel_dielectric(dot(&wo, &h), eta);
+        let wi = if fastrand::f64() < fresnel {
+            srec.lobe = Lobe::Specular;
+            srec.attenuation = Color(1.0, 1.0, 1.0);
+            reflect(&-wo, &h)
+        } else {
+            srec.lobe = Lobe::Transmission;
+            srec.attenuation = self.tint(rec);
+            unit_vector(&refract(&-wo, &h, 1.0 / eta))
+        };
+        if (wi.z() > 0.0) != (srec.lobe == Lobe::Specular) {
+            return false;
+        }
+
+        srec.attenuation = (ggx.g(&wo, &wi) / ggx.g1(&wo))
+            * srec.attenuation
+            * medium_transmittance(&self.absorption, r_in, rec);
+        srec.scattered = Ray::with_time(&rec.p, &onb.transform(&wi), r_in.time());
+        srec.pdf = self.pdf(r_in, rec, srec.scattered.direction());
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (ggx, eta, wo, wi) = self.local(r_in, rec, direction);
+        let Some((h, reflection)) = Self::half_vector(eta, &wo, &wi) else {
+            return Color::default();
+        };
+        let fresnel = microfacet::fresnel_dielectric(dot(&wo, &h), eta);
+        let dg = ggx.d(&h) * ggx.g(&wo, &wi);
+        let medium = medium_transmittance(&self.absorption, r_in, rec);
+        if reflection {
+            return (fresnel * dg / (4.0 * wo.z())) * medium;
+        }
+
+        let (cos_o, cos_i) = (dot(&wo, &h), dot(&wi, &h));
+        let denom = (cos_i + cos_o / eta).powi(2);
+        ((1.0 - fresnel) * dg * (cos_i * cos_o).abs() / (denom * wo.z())) * self.tint(rec) * medium
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (ggx, eta, wo, wi) = self.local(r_in, rec, direction);
+        let Some((h, reflection)) = Self::half_vector(eta, &wo, &wi) else {
+            return 0.0;
+        };
+        let cos_o = dot(&wo, &h);
+        let fresnel = microfacet::fresnel_dielectric(cos_o, eta);
+        if reflection {
+            return fresnel * ggx.pdf(&wo, &h) / (4.0 * cos_o);
+        }
+
+        let cos_i = dot(&wi, &h);
+        let jacobian = cos_i.abs() / (cos_i + cos_o / eta).powi(2);
+        (1.0 - fresnel) * ggx.pdf(&wo, &h) * jacobian
+    }
+}
+
+// Clear coat over any `base` material. The coat is a dielectric interface of index `ior` that
+// reflects part of the light, and lets the rest through to the base and back out again.
+// `tint` colors the light that makes that round trip, a `film` on top makes the coat
+// iridescent. The base only gets what the coat does not reflect, one minus the coat's
+// directional albedo on the way in and again on the way out, which keeps the layers from
+// reflecting more than the light they receive at any roughness.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ior: f64,
+    roughness: f64,
+    tint: Color,
+    film: Option<ThinFilm>,
+    // Tabulated on first use, from the coat parameters above
+    albedo: OnceLock<CoatAlbedo>,
+}
+
+// Directional albedo of the coat's own lobe at `COAT_ALBEDO_STEPS` cosines from 0 to 1. With
+// a film it varies over the `film_wavelength`s too, without one a single column covers all.
+struct CoatAlbedo {
+    spectral: Vec<Vec<f64>>,
+    rgb: Vec<Color>,
+}
+
+const COAT_ALBEDO_STEPS: usize = 32;
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>) -> Self {
+        Self {
+            base,
+            ior: 1.5,
+            roughness: 0.05,
+            tint: Color(1.0, 1.0, 1.0),
+            film: None,
+            albedo: OnceLock::new(),
+        }
+    }
+
+    pub fn ior(self, ior: f64) -> Self {
+        Self {
+            ior,
+            albedo: OnceLock::new(),
+            ..self
+        }
+    }
+
+    pub fn roughness(self, roughness: f64) -> Self {
+        Self {
+            roughness,
+            albedo: OnceLock::new(),
+            ..self
+        }
+    }
+
+    pub fn tint(self, tint: Color) -> Self {
+        Self { tint, ..self }
+    }
+
+    pub fn film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            albedo: OnceLock::new(),
+            ..self
+        }
+    }
+
+    fn ggx(&self) -> Ggx {
+        Ggx::new(self.roughness, 0.0)
+    }
+
+    // Reflectance of the interface at a single wavelength
+    fn fresnel_at(&self, cos_theta: f64, wavelength: f64) -> f64 {
+        match &self.film {
+            Some(film) => microfacet::fresnel_thin_film(
+                cos_theta,
+                film.ior,
+                film.thickness,
+                self.ior,
+                0.0,
+                wavelength,
+            ),
+            None => microfacet::fresnel_dielectric(cos_theta, self.ior),
+        }
+    }
+
+    fn fresnel(&self, cos_theta: f64, r_in: &Ray) -> Color {
+        match &self.film {
+            Some(film) => film.reflectance(
+                cos_theta,
+                &Color(self.ior, self.ior, self.ior),
+                &Color::default(),
+                r_in.wavelengths(),
+            ),
+            None => {
+                let f = microfacet::fresnel_dielectric(cos_theta, self.ior);
+                Color(f, f, f)
+            }
+        }
+    }
+
+    // The coat lobe integrated over a fixed grid of visible normals, the sampling density
+    // cancelling all but F G2 / G1
+    fn tabulate_albedo(&self) -> CoatAlbedo {
+        let ggx = self.ggx();
+        let grid = 8;
+        let wavelengths: Vec<f64> = match self.film {
+            Some(_) => (0..FILM_STEPS).map(film_wavelength).collect(),
+            None => vec![550.0],
+        };
+        let spectral: Vec<Vec<f64>> = (0..COAT_ALBEDO_STEPS)
+            .map(|i| {
+                let cos = (i as f64 / (COAT_ALBEDO_STEPS - 1) as f64).max(1e-3);
+                let wo = Vec3((1.0 - cos * cos).sqrt(), 0.0, cos);
+                let mut sums = vec![0.0; wavelengths.len()];
+                for (a, b) in (0..grid).flat_map(|a| (0..grid).map(move |b| (a, b))) {
+                    let (u1, u2) = (
+                        (a as f64 + 0.5) / grid as f64,
+                        (b as f64 + 0.5) / grid as f64,
+                    );
+                    let h = ggx.sample_visible(&wo, u1, u2);
+                    let wi = reflect(&-wo, &h);
+                    if wi.z() <= 0.0 {
+                        continue;
+                    }
+                    let weight = ggx.g(&wo, &wi) / ggx.g1(&wo);
+                    for (sum, wavelength) in sums.iter_mut().zip(&wavelengths) {
+                        *sum += weight * self.fresnel_at(dot(&wo, &h), *wavelength);
+                    }
+                }
+                sums.iter().map(|sum| sum / (grid * grid) as f64).collect()
+            })
+            .collect();
+        let rgb = spectral
+            .iter()
+            .map(|row| match row[..] {
+                [e] => Color(e, e, e),
+                _ => film_rgb(row),
+            })
+            .collect();
+        CoatAlbedo { spectral, rgb }
+    }
+
+    // Share of the light arriving from `cos_theta` that the coat reflects, in the form the
+    // coat returns its colors in for `r_in`
+    fn albedo(&self, cos_theta: f64, r_in: &Ray) -> Color {
+        let table = self.albedo.get_or_init(|| self.tabulate_albedo());
+        let x = cos_theta.clamp(0.0, 1.0) * (COAT_ALBEDO_STEPS - 1) as f64;
+        let i = (x as usize).min(COAT_ALBEDO_STEPS - 2);
+        let t = x - i as f64;
+        let lerp = |row: &dyn Fn(usize) -> f64| (1.0 - t) * row(i) + t * row(i + 1);
+
+        let at = |wavelength: f64| {
+            lerp(&|i| {
+                let row = &table.spectral[i];
+                let x = (wavelength - spectrum::WAVELENGTH_MIN)
+                    / (spectrum::WAVELENGTH_MAX - spectrum::WAVELENGTH_MIN)
+                    * row.len() as f64
+                    - 0.5;
+                let j = (x.max(0.0) as usize).min(row.len() - 1);
+                let k = (j + 1).min(row.len() - 1);
+                let f = (x - j as f64).clamp(0.0, 1.0);
+                (1.0 - f) * row[j] + f * row[k]
+            })
+        };
+        match r_in.wavelengths() {
+            Some(Wavelengths::Single(wavelength)) => {
+                let e = at(wavelength);
+                Color(e, e, e)
+            }
+            Some(wavelengths) if self.spectral(r_in) => {
+                let [a, b, c] = wavelengths.all();
+                Color(at(a), at(b), at(c))
+            }
+            _ => (1.0 - t) * table.rgb[i] + t * table.rgb[i + 1],
+        }
+    }
+
+    // `color` from the base, or the tint when `spectral` is false, in the form the coat returns
+    // its colors in for `r_in`
+    fn to_path(&self, r_in: &Ray, color: &Color, spectral: bool) -> Color {
+        match r_in.wavelengths() {
+            Some(wavelengths) if !spectral && self.spectral(r_in) => wavelengths.sample_rgb(color),
+            _ => *color,
+        }
+    }
+
+    // Share of the light that crosses the coat on the way to the base and back. Light the
+    // base transmits leaves through its far side without crossing the coat again.
+    fn base_weight(&self, r_in: &Ray, wo: &Vec3, wi: &Vec3) -> Color {
+        let white = Color(1.0, 1.0, 1.0);
+        let through = |w: &Vec3| {
+            if w.z() > 0.0 {
+                white - self.albedo(w.z(), r_in)
+            } else {
+                white
+            }
+        };
+        through(wo) * through(wi) * self.to_path(r_in, &self.tint, false)
+    }
+
+    // Chance of sampling the coat rather than the base, by the share of light it reflects
+    fn coat_probability(&self, wo: &Vec3, r_in: &Ray) -> f64 {
+        luminance(&self.albedo(wo.z(), r_in)).clamp(0.0, 0.9)
+    }
+
+    fn local(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Vec3, Vec3) {
+        let onb = rec.shading_frame();
+        (
+            onb.to_local(&-unit_vector(r_in.direction())),
+            onb.to_local(&unit_vector(direction)),
+        )
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let onb = rec.shading_frame();
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let coat_probability = self.coat_probability(&wo, r_in);
+
+        let direction = if fastrand::f64() < coat_probability {
+            let h = self
+                .ggx()
+                .sample_visible(&wo, fastrand::f64(), fastrand::f64());
+            let wi = reflect(&-wo, &h);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            let direction = onb.transform(&wi);
+            srec.scattered = Ray::with_time(&rec.p, &direction, r_in.time());
+            srec.lobe = Lobe::Specular;
+            direction
+        } else {
+            if !self.base.scatter(r_in, rec, srec) {
+                return false;
+            }
+            let direction = *srec.scattered.direction();
+            // Lobes that light sampling cannot reach keep the base's own weight
+            if srec.pdf == 0.0 {
+                let wi = onb.to_local(&unit_vector(&direction));
+                let attenuation = self.to_path(r_in, &srec.attenuation, self.base.spectral(r_in));
+                srec.attenuation =
+                    self.base_weight(r_in, &wo, &wi) * attenuation / (1.0 - coat_probability);
+                return true;
+            }
+            direction
+        };
+
+        // Everything else is weighted by the whole BSDF over the density of both strategies
+        let pdf = self.pdf(r_in, rec, &direction);
+        if pdf <= 0.0 {
+            return false;
+        }
+        srec.attenuation = self.eval(r_in, rec, &direction) / pdf;
+        srec.pdf = pdf;
+        true
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let wo = -unit_vector(r_in.direction());
+        let through = Color(1.0, 1.0, 1.0) - self.albedo(dot(&wo, &rec.shading_normal), r_in);
+        let emitted = self.base.emitted(r_in, rec);
+        through
+            * self.to_path(r_in, &self.tint, false)
+            * self.to_path(r_in, &emitted, self.base.spectral(r_in))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = self.local(r_in, rec, direction);
+        if wo.z() <= 0.0 {
+            return Color::default();
+        }
+        let coat = if wi.z() > 0.0 {
+            let ggx = self.ggx();
+            let h = unit_vector(&(wo + wi));
+            (ggx.d(&h) * ggx.g(&wo, &wi) / (4.0 * wo.z())) * self.fresnel(dot(&wo, &h), r_in)
+        } else {
+            Color::default()
+        };
+        let base = self.base.eval(r_in, rec, direction);
+        coat + self.base_weight(r_in, &wo, &wi)
+            * self.to_path(r_in, &base, self.base.spectral(r_in))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (wo, wi) = self.local(r_in, rec, direction);
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        let coat = if wi.z() > 0.0 {
+            let h = unit_vector(&(wo + wi));
+            self.ggx().pdf(&wo, &h) / (4.0 * dot(&wo, &h))
+        } else {
+            0.0
+        };
+        let coat_probability = self.coat_probability(&wo, r_in);
+        coat_probability * coat + (1.0 - coat_probability) * self.base.pdf(r_in, rec, direction)
+    }
+
+    // Normal and bump maps on the base show through, with the coat following the surface
+    // they describe
+    fn perturb(&self, r_in: &Ray, rec: &mut HitRecord) {
+        self.base.perturb(r_in, rec);
+    }
+
+    fn spectral(&self, r_in: &Ray) -> bool {
+        (self.film.is_some() || self.base.spectral(r_in)) && hero_wavelengths(r_in)
+    }
+}
+
+// Free-standing film with air on both sides, like the skin of a soap bubble. Light either
+// reflects off it with the film's reflectance or passes straight through.
+#[derive(Debug, Clone, Copy)]
+pub struct SoapFilm {
+    pub film: ThinFilm,
+}
+
+impl Default for SoapFilm {
+    fn default() -> Self {
+        Self {
+            film: ThinFilm {
+                thickness: 400.0,
+                ior: 1.33,
+            },
+        }
+    }
+}
+
+impl Material for SoapFilm {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let unit_direction = unit_vector(r_in.direction());
+        let cos_theta = -dot(&unit_direction, &rec.shading_normal);
+        let reflectance = self.film.reflectance(
+            cos_theta,
+            &Color(1.0, 1.0, 1.0),
+            &Color::default(),
+            r_in.wavelengths(),
+        );
+
+        let p = luminance(&reflectance);
+        let direction = if fastrand::f64() < p {
+            srec.lobe = Lobe::Specular;
+            srec.attenuation = reflectance / p;
+            reflect(&unit_direction, &rec.shading_normal)
+        } else {
+            srec.lobe = Lobe::Transmission;
+            srec.attenuation = (Color(1.0, 1.0, 1.0) - reflectance) / (1.0 - p);
+            unit_direction
+        };
+        srec.scattered = Ray::with_time(&rec.p, &direction, r_in.time());
+        true
+    }
+
+    fn spectral(&self, r_in: &Ray) -> bool {
+        hero_wavelengths(r_in)
+    }
+}
+
+// Beer-Lambert coefficients per unit distance that leave `transmittance` after `distance`
+fn absorption(transmittance: &Color, distance: f64) -> Color {
+    let coefficient = |t: f64| -t.clamp(1e-6, 1.0).ln() / distance;
+    Color(
+        coefficient(transmittance.x()),
+        coefficient(transmittance.y()),
+        coefficient(transmittance.z()),
+    )
+}
+
+// Light surviving the stretch of `r_in` inside a medium, which ends where it hits a back face.
+// Stretches ending on a front face, even one nested inside the medium, count as outside it.
+fn medium_transmittance(absorption: &Color, r_in: &Ray, rec: &HitRecord) -> Color {
+    if rec.front_face || absorption.near_zero() {
+        return Color(1.0, 1.0, 1.0);
+    }
+    let distance = rec.t * r_in.direction().length();
+    Color(
+        (-absorption.x() * distance).exp(),
+        (-absorption.y() * distance).exp(),
+        (-absorption.z() * distance).exp(),
+    )
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+        false
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::default()
+        }
+    }
+}
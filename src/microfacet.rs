@@ -0,0 +1,236 @@
+use crate::{
+    color::Color,
+    vec3::{cross, dot, unit_vector, Vec3},
+};
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+// Anisotropic GGX (Trowbridge-Reitz) distribution of microfacet normals, evaluated in a
+// shading frame with the surface normal along z.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    // Perceptual `roughness` in [0, 1] is squared into alpha, `anisotropy` in [-1, 1]
+    // stretches the highlight along x for positive values and along y for negative ones
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(-1.0, 1.0)).sqrt();
+        Self {
+            alpha_x: (alpha / aspect).max(1e-3),
+            alpha_y: (alpha * aspect).max(1e-3),
+        }
+    }
+
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = (h.x() / self.alpha_x, h.y() / self.alpha_y);
+        let e = x * x + y * y + h.z() * h.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return f64::INFINITY;
+        }
+        let (x, y) = (self.alpha_x * w.x(), self.alpha_y * w.y());
+        0.5 * (-1.0 + (1.0 + (x * x + y * y) / (w.z() * w.z())).sqrt())
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated masking and shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the visible normal `h` as seen from `wo`
+    pub fn pdf(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        let cos_o = dot(wo, h);
+        if wo.z() <= 0.0 || cos_o <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * cos_o * self.d(h) / wo.z()
+    }
+
+    // Samples a normal visible from `wo` (Heitz 2018)
+    pub fn sample_visible(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view so the distribution becomes the hemisphere of unit roughness
+        let v = unit_vector(&Vec3(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()));
+        let len_sq = v.x() * v.x() + v.y() * v.y();
+        let t1 = if len_sq > 0.0 {
+            Vec3(-v.y(), v.x(), 0.0) / len_sq.sqrt()
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(&v, &t1);
+
+        // Uniform disk sample, squashed onto the part of the hemisphere facing the view
+        let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        unit_vector(&Vec3(
+            self.alpha_x * n.x(),
+            self.alpha_y * n.y(),
+            n.z().max(1e-6),
+        ))
+    }
+}
+
+// Unpolarized Fresnel reflectance of a conductor with complex index `eta + ik`
+pub fn fresnel_conductor(cos_theta: f64, eta: &Color, k: &Color) -> Color {
+    let reflectance = |eta: f64, k: f64| {
+        let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Color(
+        reflectance(eta.x(), k.x()),
+        reflectance(eta.y(), k.y()),
+        reflectance(eta.z(), k.z()),
+    )
+}
+
+// Unpolarized Fresnel reflectance at an interface with relative index `eta`, the index on
+// the far side over the index on the side `cos_theta_i` is measured from
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Reflectance of a film `thickness` nanometers thick with index `film_ior`, lying between air
+// and a base of complex index `eta + ik`, for light of `wavelength` nanometers arriving at
+// `cos_theta`. Waves bouncing back and forth inside the film interfere (Airy summation).
+pub fn fresnel_thin_film(
+    cos_theta: f64,
+    film_ior: f64,
+    thickness: f64,
+    eta: f64,
+    k: f64,
+    wavelength: f64,
+) -> f64 {
+    let cos1 = Complex::real(cos_theta.clamp(0.0, 1.0));
+    let sin2 = Complex::real(1.0 - cos_theta.clamp(0.0, 1.0).powi(2));
+    let (n2, n3) = (Complex::real(film_ior), Complex::new(eta, k));
+    let (n2_sq, n3_sq) = (n2 * n2, n3 * n3);
+    // Index times cosine of the angle in the film and the base, by Snell's law
+    let n2_cos2 = (n2_sq - sin2).sqrt();
+    let n3_cos3 = (n3_sq - sin2).sqrt();
+
+    let r12_s = (cos1 - n2_cos2) / (cos1 + n2_cos2);
+    let r12_p = (n2_sq * cos1 - n2_cos2) / (n2_sq * cos1 + n2_cos2);
+    let r23_s = (n2_cos2 - n3_cos3) / (n2_cos2 + n3_cos3);
+    let r23_p = (n3_sq * n2_cos2 - n2_sq * n3_cos3) / (n3_sq * n2_cos2 + n2_sq * n3_cos3);
+
+    // Phase picked up over one round trip through the film
+    let delta = Complex::real(4.0 * PI * thickness / wavelength) * n2_cos2;
+    let phase = Complex::new(-delta.im, delta.re).exp();
+    let airy = |r12: Complex, r23: Complex| {
+        ((r12 + r23 * phase) / (Complex::real(1.0) + r12 * r23 * phase)).norm_sqr()
+    };
+    0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root, with a non-negative imaginary part for waves that decay
+    fn sqrt(&self) -> Self {
+        let r = self.re.hypot(self.im);
+        Self::new(
+            (0.5 * (r + self.re)).max(0.0).sqrt(),
+            (0.5 * (r - self.re)).max(0.0).sqrt().copysign(self.im),
+        )
+    }
+
+    fn exp(&self) -> Self {
+        let m = self.re.exp();
+        Self::new(m * self.im.cos(), m * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let d = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
@@ -0,0 +1,48 @@
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+// Orthonormal basis with `w` along a given direction
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = unit_vector(n);
+        let a = if w.x().abs() > 0.9 {
+            Vec3(0.0, 1.0, 0.0)
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(&cross(&w, &a));
+        let u = cross(&w, &v);
+        Self { u, v, w }
+    }
+
+    // Basis with `u` along `t` projected onto the plane normal to `n`, and `v = w × u`
+    pub fn from_tangent(n: &Vec3, t: &Vec3) -> Self {
+        let w = unit_vector(n);
+        let t = *t - dot(t, &w) * w;
+        if t.near_zero() {
+            return Self::new(n);
+        }
+        let u = unit_vector(&t);
+        Self {
+            u,
+            v: cross(&w, &u),
+            w,
+        }
+    }
+
+    // Maps coordinates in the basis to world space
+    pub fn transform(&self, p: &Vec3) -> Vec3 {
+        p.x() * self.u + p.y() * self.v + p.z() * self.w
+    }
+
+    // Coordinates of a world space vector in the basis
+    pub fn to_local(&self, p: &Vec3) -> Vec3 {
+        Vec3(dot(p, &self.u), dot(p, &self.v), dot(p, &self.w))
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    color::{luminance, Color},
+    hittable::HitRecord,
+    material::{Lobe, Material, RoughDielectric, ScatterRecord},
+    microfacet::Ggx,
+    ray::Ray,
+    vec3::{dot, random_unit_vector, reflect, unit_vector, Vec3},
+};
+use std::f64::consts::PI;
+
+// Disney-style principled surface (Burley 2012, 2015) blending a retro-reflective diffuse base
+// with sheen, a GGX specular lobe, rough glass for `transmission` and a clear coat on top.
+// Parameters other than the colors and `ior` range over [0, 1]. `specular` sets the
+// reflectance of opaque dielectrics, 0.5 giving the usual 4%, while `ior` drives the glass.
+#[derive(Debug, Clone, Copy)]
+pub struct Principled {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub anisotropy: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub emission: Color,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Color(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropy: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+            emission: Color::default(),
+        }
+    }
+}
+
+// Probabilities with which `scatter` picks each lobe for a given outgoing direction
+struct LobeWeights {
+    diffuse: f64,
+    specular: f64,
+    glass: f64,
+    clearcoat: f64,
+}
+
+impl Principled {
+    // Base color normalized to unit luminance, for the tint parameters
+    fn tint(&self) -> Color {
+        let l = luminance(&self.base_color);
+        if l > 0.0 {
+            self.base_color / l
+        } else {
+            Color(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn specular_f0(&self) -> Color {
+        let dielectric =
+            0.08 * self.specular * lerp(&Color(1.0, 1.0, 1.0), &self.tint(), self.specular_tint);
+        lerp(&dielectric, &self.base_color, self.metallic)
+    }
+
+    fn glass(&self) -> RoughDielectric {
+        RoughDielectric {
+            refractive_index: self.ior,
+            albedo: self.base_color,
+            absorption: Color::default(),
+            roughness: self.roughness,
+            anisotropy: self.anisotropy,
+        }
+    }
+
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn glass_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    // Light the clear coat reflects no longer reaches the layers below it
+    fn base_weight(&self, wo: &Vec3) -> f64 {
+        1.0 - self.clearcoat * schlick(0.04, wo.z())
+    }
+
+    fn lobe_weights(&self, wo: &Vec3) -> Option<LobeWeights> {
+        let base = self.base_weight(wo);
+        let specular_f0 = self.specular_f0();
+        let specular_fresnel =
+            specular_f0 + schlick_weight(wo.z()) * (Color(1.0, 1.0, 1.0) - specular_f0);
+
+        let diffuse = base * self.diffuse_weight() * (luminance(&self.base_color) + self.sheen);
+        let specular = base * (1.0 - self.glass_weight()) * luminance(&specular_fresnel);
+        let glass = base * self.glass_weight();
+        let clearcoat = self.clearcoat * schlick(0.04, wo.z());
+
+        let total = diffuse + specular + glass + clearcoat;
+        (total > 0.0).then(|| LobeWeights {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            glass: glass / total,
+            clearcoat: clearcoat / total,
+        })
+    }
+
+    // Everything but the glass, which works in world space through `RoughDielectric`
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+        let h = unit_vector(&(*wo + *wi));
+        let cos_d = dot(wi, &h);
+
+        let diffuse = if self.diffuse_weight() > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = |cos: f64| 1.0 + (fd90 - 1.0) * schlick_weight(cos);
+            let sheen_color = lerp(&Color(1.0, 1.0, 1.0), &self.tint(), self.sheen_tint);
+            let sheen = (self.sheen * schlick_weight(cos_d)) * sheen_color;
+            (self.diffuse_weight() * wi.z())
+                * ((retro(wo.z()) * retro(wi.z()) / PI) * self.base_color + sheen)
+        } else {
+            Color::default()
+        };
+
+        let ggx = Ggx::new(self.roughness, self.anisotropy);
+        let specular_f0 = self.specular_f0();
+        let fresnel = specular_f0 + schlick_weight(cos_d) * (Color(1.0, 1.0, 1.0) - specular_f0);
+        let specular =
+            ((1.0 - self.glass_weight()) * ggx.d(&h) * ggx.g(wo, wi) / (4.0 * wo.z())) * fresnel;
+
+        let clearcoat = if self.clearcoat > 0.0 {
+            let coat = Ggx::new(self.clearcoat_roughness, 0.0);
+            self.clearcoat * schlick(0.04, cos_d) * coat.d(&h) * coat.g(wo, wi) / (4.0 * wo.z())
+        } else {
+            0.0
+        };
+
+        self.base_weight(wo) * (diffuse + specular) + Color(clearcoat, clearcoat, clearcoat)
+    }
+
+    fn pdf_local(&self, weights: &LobeWeights, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(&(*wo + *wi));
+        let reflection = |ggx: Ggx| ggx.pdf(wo, &h) / (4.0 * dot(wo, &h));
+        weights.diffuse * wi.z() / PI
+            + weights.specular * reflection(Ggx::new(self.roughness, self.anisotropy))
+            + weights.clearcoat * reflection(Ggx::new(self.clearcoat_roughness, 0.0))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let onb = rec.shading_frame();
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let Some(weights) = self.lobe_weights(&wo) else {
+            return false;
+        };
+
+        // Pick one lobe to sample, then weight by the full BSDF over the mixture density
+        let u = fastrand::f64();
+        let direction = if u < weights.diffuse {
+            srec.lobe = Lobe::Diffuse;
+            let wi = Vec3(0.0, 0.0, 1.0) + random_unit_vector();
+            if wi.near_zero() {
+                return false;
+            }
+            onb.transform(&wi)
+        } else if u < weights.diffuse + weights.specular {
+            srec.lobe = Lobe::Specular;
+            let ggx = Ggx::new(self.roughness, self.anisotropy);
+            let h = ggx.sample_visible(&wo, fastrand::f64(), fastrand::f64());
+            let wi = reflect(&-wo, &h);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            onb.transform(&wi)
+        } else if u < weights.diffuse + weights.specular + weights.glass {
+            let mut glass = ScatterRecord::default();
+            if !self.glass().scatter(r_in, rec, &mut glass) {
+                return false;
+            }
+            srec.lobe = glass.lobe;
+            *glass.scattered.direction()
+        } else {
+            srec.lobe = Lobe::Specular;
+            let coat = Ggx::new(self.clearcoat_roughness, 0.0);
+            let h = coat.sample_visible(&wo, fastrand::f64(), fastrand::f64());
+            let wi = reflect(&-wo, &h);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            onb.transform(&wi)
+        };
+
+        let pdf = self.pdf(r_in, rec, &direction);
+        if pdf <= 0.0 {
+            return false;
+        }
+        srec.attenuation = self.eval(r_in, rec, &direction) / pdf;
+        srec.scattered = Ray::with_time(&rec.p, &direction, r_in.time());
+        srec.pdf = pdf;
+        true
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emission
+        } else {
+            Color::default()
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let onb = rec.shading_frame();
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        let wi = onb.to_local(&unit_vector(direction));
+        let mut f = self.eval_local(&wo, &wi);
+        if self.glass_weight() > 0.0 {
+            f += (self.base_weight(&wo) * self.glass_weight())
+                * self.glass().eval(r_in, rec, direction);
+        }
+        f
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let onb = rec.shading_frame();
+        let wo = onb.to_local(&-unit_vector(r_in.direction()));
+        let wi = onb.to_local(&unit_vector(direction));
+        let Some(weights) = self.lobe_weights(&wo) else {
+            return 0.0;
+        };
+        let mut pdf = self.pdf_local(&weights, &wo, &wi);
+        if weights.glass > 0.0 {
+            pdf += weights.glass * self.glass().pdf(r_in, rec, direction);
+        }
+        pdf
+    }
+}
+
+fn lerp(a: &Color, b: &Color, t: f64) -> Color {
+    (1.0 - t) * *a + t * *b
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(f0: f64, cos: f64) -> f64 {
+    f0 + (1.0 - f0) * schlick_weight(cos)
+}
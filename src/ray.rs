@@ -1,9 +1,15 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    spectrum::Wavelengths,
+    vec3::{Point3, Vec3},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    tm: f64,
+    // Wavelengths in nanometers, once spectral rendering or dispersion has picked them
+    wavelengths: Option<Wavelengths>,
 }
 
 impl Default for Ray {
@@ -11,15 +17,30 @@ impl Default for Ray {
         Self {
             orig: Default::default(),
             dir: Vec3(0.0, 0.0, 1.0),
+            tm: 0.0,
+            wavelengths: None,
         }
     }
 }
 
 impl Ray {
     pub fn new(origin: &Point3, direction: &Vec3) -> Self {
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: &Point3, direction: &Vec3, time: f64) -> Self {
         Self {
             orig: *origin,
             dir: *direction,
+            tm: time,
+            wavelengths: None,
+        }
+    }
+
+    pub fn with_wavelengths(self, wavelengths: Option<Wavelengths>) -> Self {
+        Self {
+            wavelengths,
+            ..self
         }
     }
 
@@ -31,6 +52,19 @@ impl Ray {
         &self.dir
     }
 
+    pub const fn time(&self) -> f64 {
+        self.tm
+    }
+
+    pub const fn wavelengths(&self) -> Option<Wavelengths> {
+        self.wavelengths
+    }
+
+    // The hero wavelength
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelengths.map(|wavelengths| wavelengths.hero())
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
@@ -0,0 +1,116 @@
+// Piecewise-constant distributions over [0, 1) and [0, 1)^2 for sampling tabulated functions.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f64]) -> Self {
+        assert!(!func.is_empty(), "Distribution needs at least one value");
+        let n = func.len();
+        let func: Vec<f64> = func.iter().map(|f| f.abs()).collect();
+
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f64;
+        }
+        let integral = cdf[n];
+
+        // A zero function falls back to uniform sampling
+        if integral == 0.0 {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n as f64;
+            }
+        } else {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Returns the sampled position, its density and the index of the segment it fell in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = (offset as f64 + du) / self.count() as f64;
+        (x, self.pdf_at(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let offset = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.pdf_at(offset)
+    }
+
+    fn pdf_at(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// Rows of `func` are indexed by v, columns by u.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        assert_eq!(func.len(), nu * nv, "Distribution size mismatch");
+        let conditional: Vec<Distribution1D> = func.chunks(nu).map(Distribution1D::new).collect();
+        let marginal: Vec<f64> = conditional.iter().map(|c| c.integral()).collect();
+        Self {
+            conditional,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    pub fn sample(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+// Power heuristic weight (beta = 2) for a sample drawn with density `f_pdf` that another
+// strategy could have drawn with density `g_pdf`.
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let (f, g) = (f_pdf * f_pdf, g_pdf * g_pdf);
+    if f + g == 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
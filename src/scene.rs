@@ -0,0 +1,56 @@
+use crate::{
+    hittable::Hittable, hittable_list::HittableList, light_list::LightList, material::Material,
+};
+use std::{collections::HashMap, sync::Arc};
+
+// Everything a camera renders: the objects rays can hit and the punctual lights sampled at
+// every bounce. Scenes are kept apart from cameras so that several viewpoints can share one.
+#[derive(Default)]
+pub struct Scene {
+    world: HittableList,
+    lights: LightList,
+    // Materials numbered in the order the objects using them were added, keyed by address, so
+    // IDs stay the same from run to run
+    material_ids: HashMap<usize, u32>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, lights: LightList) -> Self {
+        let mut material_ids = HashMap::new();
+        for material in world.materials() {
+            let next = material_ids.len() as u32;
+            material_ids.entry(address(&material)).or_insert(next);
+        }
+        Self {
+            world,
+            lights,
+            material_ids,
+        }
+    }
+
+    pub fn world(&self) -> &HittableList {
+        &self.world
+    }
+
+    pub fn lights(&self) -> &LightList {
+        &self.lights
+    }
+
+    // Materials of objects that do not list them share the last ID
+    pub fn material_id(&self, material: &Arc<dyn Material>) -> u32 {
+        self.material_ids
+            .get(&address(material))
+            .copied()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+impl From<HittableList> for Scene {
+    fn from(world: HittableList) -> Self {
+        Self::new(world, LightList::default())
+    }
+}
+
+fn address(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
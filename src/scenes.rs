@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
 use crate::{
+    animation::{Interpolation, Track},
     color::Color,
     hittable_list::HittableList,
     material::{Dielectric, Lambertian, Material, Metal},
     sphere::Sphere,
-    vec3::Point3,
+    transform::AnimatedTransform,
+    vec3::{Point3, Vec3},
 };
 
 pub fn default_scene() -> HittableList {
@@ -21,6 +23,7 @@ pub fn default_scene() -> HittableList {
     let material_left = Arc::new(Dielectric {
         refractive_index: 1.5,
         albedo: Color(1.0, 1.0, 1.0),
+        ..Default::default()
     });
 
     let material_bubble = Arc::new(Dielectric {
@@ -110,3 +113,38 @@ pub fn test_scene() -> HittableList {
 
     world
 }
+
+// A ball bouncing in front of the camera over 24 frames, squashed on impact
+pub fn bouncing_scene() -> HittableList {
+    let mut world = HittableList::default();
+
+    let material_ground = Arc::new(Lambertian {
+        albedo: Color(0.8, 0.8, 0.0),
+    });
+    let material_ball = Arc::new(Lambertian {
+        albedo: Color(0.1, 0.2, 0.5),
+    });
+
+    world.add(Sphere::new(
+        &Point3(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    let translation = Track::new(Interpolation::CatmullRom)
+        .key(0.0, Vec3(-1.0, 0.0, -1.2))
+        .key(6.0, Vec3(-0.5, 0.8, -1.2))
+        .key(12.0, Vec3(0.0, -0.1, -1.2))
+        .key(18.0, Vec3(0.5, 0.8, -1.2))
+        .key(24.0, Vec3(1.0, 0.0, -1.2));
+    let scale = Track::new(Interpolation::Linear)
+        .key(10.0, Vec3(1.0, 1.0, 1.0))
+        .key(12.0, Vec3(1.2, 0.8, 1.2))
+        .key(14.0, Vec3(1.0, 1.0, 1.0));
+    let ball = AnimatedTransform::new(Sphere::new(&Point3(0.0, 0.0, 0.0), 0.5, material_ball))
+        .translation(translation)
+        .scale(scale);
+    world.add(Arc::new(ball));
+
+    world
+}
@@ -0,0 +1,264 @@
+use crate::{
+    color::{luminance, Color},
+    degrees_to_radians,
+    environment::{Environment, EnvironmentMap},
+    image::ImageInfo,
+    onb::Onb,
+    spectrum,
+    vec3::{dot, unit_vector, Vec3},
+};
+use std::f64::consts::PI;
+
+// Angular radius of the sun disk
+const SUN_RADIUS: f64 = 0.004_675;
+// Luminance of the sun above the atmosphere, in kcd/m^2
+const SUN_LUMINANCE: f64 = 2.0e6;
+// Wavelengths in micrometers standing in for the red, green and blue primaries
+const WAVELENGTHS: [f64; 3] = [0.680, 0.550, 0.440];
+
+// Preetham et al. 1999 clear sky with a sun disk, over a ground lit by both. Radiances are
+// in kcd/m^2 scaled by `intensity`, the default of which maps a white surface under a
+// high sun to about one. The sky is +y up with north towards -z and east towards +x.
+#[derive(Debug, Clone)]
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    intensity: f64,
+    // Perez coefficients A to E for Y, x and y, followed by their zenith values
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    ground_radiance: Color,
+    // Sky and ground without the sun, tabulated for importance sampling
+    sampling: EnvironmentMap,
+    sun_probability: f64,
+}
+
+impl PhysicalSky {
+    // `turbidity` ranges from 2 for a very clear sky to about 10 for a hazy one
+    pub fn new(sun_direction: &Vec3, turbidity: f64, ground_albedo: Color) -> Self {
+        let sun_direction = unit_vector(sun_direction);
+        let t = turbidity;
+        // The model only covers a sun above the horizon
+        let theta_s = sun_direction
+            .y()
+            .clamp(0.0, 1.0)
+            .acos()
+            .min(PI / 2.0 - 1e-3);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |c: [[f64; 4]; 3]| {
+            let cubic = |k: [f64; 4]| {
+                k[0] * theta_s.powi(3) + k[1] * theta_s.powi(2) + k[2] * theta_s + k[3]
+            };
+            t * t * cubic(c[0]) + t * cubic(c[1]) + cubic(c[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        // Sunlight dimmed by Rayleigh and aerosol scattering along its path through the air
+        let sun_radiance = if sun_direction.y() > 0.0 {
+            let zenith_angle = sun_direction.y().acos().to_degrees();
+            let air_mass = 1.0 / (sun_direction.y() + 0.15 * (93.885 - zenith_angle).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = WAVELENGTHS.map(|lambda: f64| {
+                let rayleigh = -0.008735 * lambda.powf(-4.08) * air_mass;
+                let aerosol = -beta * lambda.powf(-1.3) * air_mass;
+                (rayleigh + aerosol).exp()
+            });
+            SUN_LUMINANCE * Color(transmittance[0], transmittance[1], transmittance[2])
+        } else {
+            Color::default()
+        };
+
+        let mut sky = Self {
+            sun_direction,
+            intensity: 0.025,
+            perez,
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y],
+            sun_radiance,
+            ground_radiance: Color::default(),
+            sampling: EnvironmentMap::new(&ImageInfo::from_dim(1, 1), vec![Color::default()]),
+            sun_probability: 0.0,
+        };
+
+        // The ground reflects the sun and sky falling on it diffusely
+        let (nu, nv) = (128, 32);
+        let mut sky_irradiance = Color::default();
+        for j in 0..nv {
+            let theta = 0.5 * PI * (j as f64 + 0.5) / nv as f64;
+            let solid_angle = (2.0 * PI / nu as f64) * (0.5 * PI / nv as f64) * theta.sin();
+            for i in 0..nu {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / nu as f64;
+                let d = Vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                sky_irradiance += (solid_angle * theta.cos()) * sky.sky_radiance(&d);
+            }
+        }
+        let sun_solid_angle = 2.0 * PI * (1.0 - SUN_RADIUS.cos());
+        let irradiance =
+            sky_irradiance + (sun_solid_angle * sun_direction.y().max(0.0)) * sun_radiance;
+        sky.ground_radiance = (1.0 / PI) * ground_albedo * irradiance;
+
+        // Split light samples between the sun and the rest by their share of the power
+        sky.sampling =
+            EnvironmentMap::bake(&ImageInfo::from_dim(128, 64), |d| sky.sky_or_ground(d));
+        let sky_power = sky.sampling.power();
+        let sun_power = sun_solid_angle * luminance(&sun_radiance);
+        sky.sun_probability = sun_power / (sun_power + sky_power);
+        sky
+    }
+
+    // Sun direction from the day of the year, the local solar time in hours and the latitude
+    // in degrees, after Preetham et al. Appendix A.6
+    pub fn sun_position(day_of_year: u32, solar_time: f64, latitude: f64) -> Vec3 {
+        let latitude = degrees_to_radians(latitude);
+        let declination = 0.4093 * (2.0 * PI * (day_of_year as f64 - 81.0) / 368.0).sin();
+        let hour_angle = PI * solar_time / 12.0;
+
+        let theta = PI / 2.0
+            - (latitude.sin() * declination.sin()
+                - latitude.cos() * declination.cos() * hour_angle.cos())
+            .asin();
+        // Azimuth measured from south towards west
+        let phi = (-declination.cos() * hour_angle.sin()).atan2(
+            latitude.cos() * declination.sin()
+                - latitude.sin() * declination.cos() * hour_angle.cos(),
+        );
+        Vec3(
+            -theta.sin() * phi.sin(),
+            theta.cos(),
+            theta.sin() * phi.cos(),
+        )
+    }
+
+    pub fn intensity(self, intensity: f64) -> Self {
+        Self { intensity, ..self }
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let d = unit_vector(direction);
+        // Keep the horizon finite, the model is not defined below it
+        let cos_theta = d.y().max(0.01);
+        let cos_gamma = dot(&d, &self.sun_direction).clamp(-1.0, 1.0);
+        let (gamma, theta_s) = (
+            cos_gamma.acos(),
+            self.sun_direction.y().clamp(0.0, 1.0).acos(),
+        );
+
+        let perez = |[a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64| {
+            (1.0 + a * (b / cos_theta).exp())
+                * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+        };
+        let [luminance, x, y] = [0, 1, 2].map(|k| {
+            self.zenith[k] * perez(self.perez[k], cos_theta, gamma)
+                / perez(self.perez[k], 1.0, theta_s)
+        });
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    fn sky_or_ground(&self, direction: &Vec3) -> Color {
+        if direction.y() < 0.0 {
+            self.ground_radiance
+        } else {
+            self.sky_radiance(direction)
+        }
+    }
+
+    fn in_sun(&self, direction: &Vec3) -> bool {
+        dot(&unit_vector(direction), &self.sun_direction) >= SUN_RADIUS.cos()
+    }
+
+    fn sun_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - SUN_RADIUS.cos()))
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let mut radiance = self.sky_or_ground(direction);
+        if direction.y() >= 0.0 && self.in_sun(direction) {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * radiance
+    }
+
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        let direction = if fastrand::f64() < self.sun_probability {
+            // Uniform over the cone subtended by the sun
+            let cos_theta = 1.0 - fastrand::f64() * (1.0 - SUN_RADIUS.cos());
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * fastrand::f64();
+            Onb::new(&self.sun_direction).transform(&Vec3(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            self.sampling.sample()?.0
+        };
+
+        let pdf = self.pdf(&direction);
+        (pdf > 0.0).then(|| (direction, self.radiance(&direction), pdf))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let sun = if self.in_sun(direction) {
+            self.sun_pdf()
+        } else {
+            0.0
+        };
+        self.sun_probability * sun + (1.0 - self.sun_probability) * self.sampling.pdf(direction)
+    }
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::default();
+    }
+    let rgb = spectrum::xyz_to_rgb(&Vec3(
+        x / y * luminance,
+        luminance,
+        (1.0 - x - y) / y * luminance,
+    ));
+    Color(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
@@ -0,0 +1,219 @@
+use crate::{color::Color, vec3::Vec3};
+use std::sync::OnceLock;
+
+// Visible range wavelengths are drawn from, in nanometers
+pub const WAVELENGTH_MIN: f64 = 380.0;
+pub const WAVELENGTH_MAX: f64 = 780.0;
+
+// CIE 1931 2-degree color matching functions, after the multi-lobe Gaussian fit of Wyman
+// et al. 2013
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let t = (wavelength - mu)
+            / if wavelength < mu {
+                sigma_low
+            } else {
+                sigma_high
+            };
+        (-0.5 * t * t).exp()
+    };
+    Vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// CIE XYZ to linear sRGB, which can go negative outside the sRGB gamut
+pub fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+pub fn sample_wavelength() -> f64 {
+    WAVELENGTH_MIN + (WAVELENGTH_MAX - WAVELENGTH_MIN) * fastrand::f64()
+}
+
+// RGB that a path carrying only `wavelength` contributes, for wavelengths drawn by
+// `sample_wavelength`. Scaled so that the weights of all wavelengths average to white.
+pub fn wavelength_weight(wavelength: f64) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let steps = 4000;
+        let mut sum = Color::default();
+        for k in 0..steps {
+            let t = (k as f64 + 0.5) / steps as f64;
+            let wavelength = WAVELENGTH_MIN + t * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+            sum += xyz_to_rgb(&cie_xyz(wavelength));
+        }
+        sum / steps as f64
+    });
+    let rgb = xyz_to_rgb(&cie_xyz(wavelength));
+    Color(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+// Wavelengths a path is traced at. Spectral paths start out with a hero wavelength and two
+// companions spaced evenly over the visible range (Wilkie et al. 2014), until something
+// dispersive sends the hero off on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wavelengths {
+    Hero(f64),
+    Single(f64),
+}
+
+impl Wavelengths {
+    pub fn sample() -> Self {
+        Wavelengths::Hero(sample_wavelength())
+    }
+
+    pub fn hero(&self) -> f64 {
+        match *self {
+            Wavelengths::Hero(wavelength) | Wavelengths::Single(wavelength) => wavelength,
+        }
+    }
+
+    // The hero followed by its companions, or the single wavelength three times over
+    pub fn all(&self) -> [f64; 3] {
+        match *self {
+            Wavelengths::Hero(hero) => {
+                let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+                [0.0, 1.0, 2.0].map(|k| {
+                    WAVELENGTH_MIN + (hero - WAVELENGTH_MIN + k * range / 3.0).rem_euclid(range)
+                })
+            }
+            Wavelengths::Single(wavelength) => [wavelength; 3],
+        }
+    }
+
+    // Spectral samples of an RGB color at these wavelengths
+    pub fn sample_rgb(&self, rgb: &Color) -> Vec3 {
+        let [a, b, c] = self.all();
+        Vec3(upsample(rgb, a), upsample(rgb, b), upsample(rgb, c))
+    }
+
+    // RGB estimate from radiance sampled at these wavelengths
+    pub fn to_rgb(&self, samples: &Vec3) -> Color {
+        let [a, b, c] = self.all();
+        (samples.x() * wavelength_weight(a)
+            + samples.y() * wavelength_weight(b)
+            + samples.z() * wavelength_weight(c))
+            / 3.0
+    }
+}
+
+// Smits 1999 basis spectra, in ten bins of equal width from 380 to 720 nm
+const SMITS_START: f64 = 380.0;
+const SMITS_BIN_WIDTH: f64 = 34.0;
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value at `wavelength` of a smooth spectrum with color `rgb`, built from white and the
+// primary and secondary basis spectra (Smits 1999). Used for reflectances and emission alike.
+pub fn upsample(rgb: &Color, wavelength: f64) -> f64 {
+    let t = ((wavelength - SMITS_START) / SMITS_BIN_WIDTH - 0.5).clamp(0.0, 9.0);
+    let (i, f) = (t.floor() as usize, t.fract());
+    let basis = |spectrum: &[f64; 10]| (1.0 - f) * spectrum[i] + f * spectrum[(i + 1).min(9)];
+
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+// Refractive index as a function of wavelength. Coefficients take wavelengths in
+// micrometers, as optical glass catalogues list them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / λ^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + Σ b_i λ^2 / (λ^2 - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    pub fn fused_silica() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+            c: [0.004_679_148_26, 0.013_512_063_1, 97.934_002_5],
+        }
+    }
+
+    // Dense flint glass, Schott SF11
+    pub fn flint() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+            c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030_625, 0.011_236, 0.0],
+        }
+    }
+
+    // `wavelength` in nanometers
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength * 1e-3).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
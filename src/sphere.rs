@@ -1,9 +1,9 @@
 use crate::{
     hittable::{HitRecord, Hittable},
     material::Material,
-    vec3::{dot, Point3},
+    vec3::{dot, unit_vector, Point3, Vec3},
 };
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 pub struct Sphere {
     center: Point3,
@@ -20,6 +20,23 @@ impl Sphere {
             mat,
         })
     }
+
+    // Spherical coordinates of a point on the unit sphere, with v running from -y to +y
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Direction of increasing u at a point on the unit sphere, undefined at the poles
+    fn get_sphere_tangent(p: &Point3) -> Vec3 {
+        let t = Vec3(p.z(), 0.0, -p.x());
+        if t.near_zero() {
+            Vec3::default()
+        } else {
+            unit_vector(&t)
+        }
+    }
 }
 
 impl Hittable for Sphere {
@@ -54,8 +71,14 @@ impl Hittable for Sphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(&outward_normal);
+        rec.tangent = Sphere::get_sphere_tangent(&outward_normal);
         rec.mat = Some(self.mat.clone());
 
         true
     }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
 }
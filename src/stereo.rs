@@ -0,0 +1,69 @@
+use crate::{color::Color, image::ImageInfo};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    ToeIn,
+    #[default]
+    OffAxis,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    #[default]
+    SideBySide,
+    TopBottom,
+    Anaglyph,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    pub interocular: f64,
+    pub convergence: f64,
+    pub mode: StereoMode,
+    pub layout: StereoLayout,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            interocular: 0.065,
+            convergence: 10.0,
+            mode: StereoMode::default(),
+            layout: StereoLayout::default(),
+        }
+    }
+}
+
+impl Stereo {
+    pub fn compose(
+        &self,
+        eye_info: &ImageInfo,
+        left: &[Color],
+        right: &[Color],
+    ) -> (ImageInfo, Vec<Color>) {
+        let (width, height) = (eye_info.image_width, eye_info.image_height);
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let pixels = left
+                    .chunks(width as usize)
+                    .zip(right.chunks(width as usize))
+                    .flat_map(|(l, r)| l.iter().chain(r.iter()).copied())
+                    .collect();
+                (ImageInfo::from_dim(2 * width, height), pixels)
+            }
+            StereoLayout::TopBottom => {
+                let pixels = left.iter().chain(right.iter()).copied().collect();
+                (ImageInfo::from_dim(width, 2 * height), pixels)
+            }
+            // Red from the left eye, green and blue from the right
+            StereoLayout::Anaglyph => {
+                let pixels = left
+                    .iter()
+                    .zip(right.iter())
+                    .map(|(l, r)| Color(l.x(), r.y(), r.z()))
+                    .collect();
+                (*eye_info, pixels)
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{
+    color::{luminance, Color},
+    image::{self, ImageInfo},
+};
+use std::{
+    fs::File,
+    io::{BufReader, Error, ErrorKind},
+    path::Path,
+};
+
+// Image laid over surface (u, v) coordinates with u running left to right and v bottom to
+// top, repeating outside [0, 1]. Lookups are bilinearly filtered.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    image_info: ImageInfo,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(image_info: &ImageInfo, pixels: Vec<Color>) -> Self {
+        Self {
+            image_info: *image_info,
+            pixels,
+        }
+    }
+
+    // Loads a PNG, PNM or PFM file, picked by extension
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let extension = path.extension().and_then(|e| e.to_str());
+        let (image_info, pixels) = match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("png") => image::read_png(&mut reader)?,
+            Some("pgm" | "ppm" | "pnm") => image::read_pnm(&mut reader)?,
+            Some("pfm") => image::read_pfm(&mut reader)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Textures must be .png, .pgm, .ppm or .pfm files",
+                ))
+            }
+        };
+        Ok(Self::new(&image_info, pixels))
+    }
+
+    // Tabulates `value` at the texel centers of a texture of the given size
+    pub fn bake(image_info: &ImageInfo, value: impl Fn(f64, f64) -> Color) -> Self {
+        let (width, height) = (image_info.image_width, image_info.image_height);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                value(
+                    (x as f64 + 0.5) / width as f64,
+                    1.0 - (y as f64 + 0.5) / height as f64,
+                )
+            })
+            .collect();
+        Self::new(image_info, pixels)
+    }
+
+    pub fn value(&self, u: f64, v: f64) -> Color {
+        let (width, height) = (
+            self.image_info.image_width as i64,
+            self.image_info.image_height as i64,
+        );
+        let x = u * width as f64 - 0.5;
+        let y = (1.0 - v) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |x: i64, y: i64| {
+            self.pixels[(y.rem_euclid(height) * width + x.rem_euclid(width)) as usize]
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let row = |y: i64| (1.0 - tx) * texel(x0, y) + tx * texel(x0 + 1, y);
+        (1.0 - ty) * row(y0) + ty * row(y0 + 1)
+    }
+
+    // Derivatives of the luminance along u and v, by central differences one texel apart
+    pub fn gradient(&self, u: f64, v: f64) -> (f64, f64) {
+        let du = 1.0 / self.image_info.image_width as f64;
+        let dv = 1.0 / self.image_info.image_height as f64;
+        let height = |u: f64, v: f64| luminance(&self.value(u, v));
+        (
+            (height(u + du, v) - height(u - du, v)) / (2.0 * du),
+            (height(u, v + dv) - height(u, v - dv)) / (2.0 * dv),
+        )
+    }
+}
@@ -0,0 +1,103 @@
+use crate::image::ImageInfo;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    #[default]
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn full(image_info: &ImageInfo) -> Self {
+        Self {
+            x0: 0,
+            y0: 0,
+            x1: image_info.image_width,
+            y1: image_info.image_height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y0..self.y1).flat_map(move |j| (self.x0..self.x1).map(move |i| (i, j)))
+    }
+}
+
+pub fn tiles(bounds: &Tile, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let (nx, ny) = (
+        bounds.width().div_ceil(tile_size),
+        bounds.height().div_ceil(tile_size),
+    );
+
+    let mut grid: Vec<(u32, u32)> = (0..ny)
+        .flat_map(|ty| (0..nx).map(move |tx| (tx, ty)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // Rings of increasing distance from the center tile, each walked by angle.
+            let (cx, cy) = ((nx as f64 - 1.0) / 2.0, (ny as f64 - 1.0) / 2.0);
+            let key = |&(tx, ty): &(u32, u32)| {
+                let (dx, dy) = (tx as f64 - cx, ty as f64 - cy);
+                (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+            };
+            grid.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let n = nx.max(ny).next_power_of_two();
+            grid.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: bounds.x0 + tx * tile_size,
+            y0: bounds.y0 + ty * tile_size,
+            x1: (bounds.x0 + (tx + 1) * tile_size).min(bounds.x1),
+            y1: (bounds.y0 + (ty + 1) * tile_size).min(bounds.y1),
+        })
+        .collect()
+}
+
+fn hilbert_index(n: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            } else {
+                x &= s - 1;
+                y &= s - 1;
+            }
+            std::mem::swap(&mut x, &mut y);
+        } else {
+            x &= s - 1;
+            y &= s - 1;
+        }
+        s /= 2;
+    }
+    d
+}
@@ -0,0 +1,140 @@
+use crate::{
+    animation::Track,
+    degrees_to_radians,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::{dot, unit_vector, Vec3},
+};
+use std::{ops::RangeInclusive, sync::Arc};
+
+// Wraps an object in a translation, rotation and scale keyframed over time. Rotations are
+// XYZ Euler angles in degrees, applied after scaling and before translation.
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable + Send + Sync>,
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Vec3>,
+    pub scale: Track<Vec3>,
+}
+
+impl AnimatedTransform {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>) -> Self {
+        Self {
+            object,
+            translation: Track::default(),
+            rotation: Track::default(),
+            scale: Track::default(),
+        }
+    }
+
+    pub fn translation(self, translation: Track<Vec3>) -> Self {
+        Self {
+            translation,
+            ..self
+        }
+    }
+
+    pub fn rotation(self, rotation: Track<Vec3>) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn scale(self, scale: Track<Vec3>) -> Self {
+        Self { scale, ..self }
+    }
+
+    fn transform_at(&self, time: f64) -> Transform {
+        let translation = self.translation.evaluate(time).unwrap_or_default();
+        let rotation = self.rotation.evaluate(time).unwrap_or_default();
+        let scale = self.scale.evaluate(time).unwrap_or(Vec3(1.0, 1.0, 1.0));
+
+        let (sx, cx) = degrees_to_radians(rotation.x()).sin_cos();
+        let (sy, cy) = degrees_to_radians(rotation.y()).sin_cos();
+        let (sz, cz) = degrees_to_radians(rotation.z()).sin_cos();
+        // Columns of Rz * Ry * Rx
+        let basis = [
+            Vec3(cy * cz, cy * sz, -sy),
+            Vec3(sx * sy * cz - cx * sz, sx * sy * sz + cx * cz, sx * cy),
+            Vec3(cx * sy * cz + sx * sz, cx * sy * sz - sx * cz, cx * cy),
+        ];
+
+        Transform {
+            translation,
+            basis,
+            scale,
+        }
+    }
+}
+
+struct Transform {
+    translation: Vec3,
+    basis: [Vec3; 3],
+    scale: Vec3,
+}
+
+impl Transform {
+    fn rotate(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.basis[0] + v.y() * self.basis[1] + v.z() * self.basis[2]
+    }
+
+    fn rotate_inverse(&self, v: &Vec3) -> Vec3 {
+        Vec3(
+            dot(&self.basis[0], v),
+            dot(&self.basis[1], v),
+            dot(&self.basis[2], v),
+        )
+    }
+
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        let v = self.rotate_inverse(v);
+        Vec3(
+            v.x() / self.scale.x(),
+            v.y() / self.scale.y(),
+            v.z() / self.scale.z(),
+        )
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        self.rotate(&Vec3(
+            v.x() * self.scale.x(),
+            v.y() * self.scale.y(),
+            v.z() * self.scale.z(),
+        ))
+    }
+
+    // Normals transform with the inverse transpose
+    fn normal_to_world(&self, n: &Vec3) -> Vec3 {
+        unit_vector(&self.rotate(&Vec3(
+            n.x() / self.scale.x(),
+            n.y() / self.scale.y(),
+            n.z() / self.scale.z(),
+        )))
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: &Ray, interval: RangeInclusive<f64>, rec: &mut HitRecord) -> bool {
+        let transform = self.transform_at(r.time());
+
+        // The direction is not renormalized, so hit distances carry over unchanged
+        let object_ray = Ray::with_time(
+            &transform.to_object(&(r.origin() - transform.translation)),
+            &transform.to_object(r.direction()),
+            r.time(),
+        );
+        if !self.object.hit(&object_ray, interval, rec) {
+            return false;
+        }
+
+        rec.p = transform.to_world(&rec.p) + transform.translation;
+        rec.normal = transform.normal_to_world(&rec.normal);
+        rec.shading_normal = transform.normal_to_world(&rec.shading_normal);
+        if !rec.tangent.near_zero() {
+            rec.tangent = unit_vector(&transform.to_world(&rec.tangent));
+        }
+        true
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.object.materials()
+    }
+}
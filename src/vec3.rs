@@ -43,7 +43,7 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        return [self.0, self.1, self.2].iter().all(|e| e.abs() < s);
+        [self.0, self.1, self.2].iter().all(|e| e.abs() < s)
     }
 
     pub fn random(interval: Option<RangeInclusive<f64>>) -> Self {